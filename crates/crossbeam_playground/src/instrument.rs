@@ -0,0 +1,614 @@
+use std::{
+    fmt,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{
+    Receiver, RecvError, RecvTimeoutError, SendError, SendTimeoutError, Sender, TryRecvError,
+    TrySendError, select, tick,
+};
+
+/// A bounded channel that keeps count of what goes through it
+pub fn bounded<T>(name: &str, capacity: usize) -> (InstrumentedSender<T>, InstrumentedReceiver<T>) {
+    let (sender, receiver) = crossbeam_channel::bounded(capacity);
+    instrument(name, sender, receiver)
+}
+
+/// An unbounded channel that keeps count of what goes through it
+pub fn unbounded<T>(name: &str) -> (InstrumentedSender<T>, InstrumentedReceiver<T>) {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    instrument(name, sender, receiver)
+}
+
+/// Wrap both ends of an existing channel. They have to be the only ends of it, or whatever the
+/// other ones send and receive is missing from the counts.
+pub fn instrument<T>(
+    name: &str,
+    sender: Sender<T>,
+    receiver: Receiver<T>,
+) -> (InstrumentedSender<T>, InstrumentedReceiver<T>) {
+    let stats = ChannelStats(Arc::new(Counters::new(name, &sender)));
+    (
+        InstrumentedSender {
+            sender,
+            stats: stats.clone(),
+        },
+        InstrumentedReceiver { receiver, stats },
+    )
+}
+
+/// A `Sender` that counts what it sends and how long it waits for room to do so
+pub struct InstrumentedSender<T> {
+    sender: Sender<T>,
+    stats: ChannelStats,
+}
+
+impl<T> InstrumentedSender<T> {
+    pub fn send(&self, item: T) -> Result<(), SendError<T>> {
+        let item = match self.sender.try_send(item) {
+            Ok(()) => {
+                self.stats.0.sent(self.sender.len());
+                return Ok(());
+            }
+            Err(TrySendError::Full(item)) => item,
+            Err(TrySendError::Disconnected(item)) => {
+                self.stats.0.failed_sends.fetch_add(1, Ordering::Relaxed);
+                return Err(SendError(item));
+            }
+        };
+        let result = self.stats.0.send_waits.time(|| self.sender.send(item));
+        match &result {
+            Ok(()) => self.stats.0.sent(self.sender.len()),
+            Err(_) => {
+                self.stats.0.failed_sends.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        result
+    }
+
+    pub fn try_send(&self, item: T) -> Result<(), TrySendError<T>> {
+        let result = self.sender.try_send(item);
+        match &result {
+            Ok(()) => self.stats.0.sent(self.sender.len()),
+            Err(TrySendError::Full(_)) => {}
+            Err(TrySendError::Disconnected(_)) => {
+                self.stats.0.failed_sends.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        result
+    }
+
+    pub fn send_timeout(&self, item: T, timeout: Duration) -> Result<(), SendTimeoutError<T>> {
+        let result = self
+            .stats
+            .0
+            .send_waits
+            .time(|| self.sender.send_timeout(item, timeout));
+        match &result {
+            Ok(()) => self.stats.0.sent(self.sender.len()),
+            Err(SendTimeoutError::Timeout(_)) => {}
+            Err(SendTimeoutError::Disconnected(_)) => {
+                self.stats.0.failed_sends.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        result
+    }
+
+    /// The counters, without keeping the channel alive
+    pub fn stats(&self) -> ChannelStats {
+        self.stats.clone()
+    }
+
+    pub fn snapshot(&self) -> ChannelSnapshot {
+        self.stats.snapshot_with(self.sender.len())
+    }
+
+    /// The underlying sender, eg. for `select!`. Nothing sent on it is counted.
+    pub fn inner(&self) -> &Sender<T> {
+        &self.sender
+    }
+}
+
+impl<T> Clone for InstrumentedSender<T> {
+    fn clone(&self) -> Self {
+        self.stats.0.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            sender: self.sender.clone(),
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+impl<T> Drop for InstrumentedSender<T> {
+    fn drop(&mut self) {
+        if self.stats.0.senders.fetch_sub(1, Ordering::Relaxed) == 1 {
+            self.stats.0.disconnected(Side::Senders);
+        }
+    }
+}
+
+impl<T> fmt::Debug for InstrumentedSender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("InstrumentedSender")
+            .field(&self.snapshot())
+            .finish()
+    }
+}
+
+/// A `Receiver` that counts what it receives and how long it waits for something to come in
+pub struct InstrumentedReceiver<T> {
+    receiver: Receiver<T>,
+    stats: ChannelStats,
+}
+
+impl<T> InstrumentedReceiver<T> {
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let result = match self.receiver.try_recv() {
+            Ok(item) => Ok(item),
+            Err(TryRecvError::Empty) => self.stats.0.recv_waits.time(|| self.receiver.recv()),
+            Err(TryRecvError::Disconnected) => Err(RecvError),
+        };
+        if result.is_ok() {
+            self.stats.0.received(self.receiver.len());
+        }
+        result
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let result = self.receiver.try_recv();
+        if result.is_ok() {
+            self.stats.0.received(self.receiver.len());
+        }
+        result
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        let result = self
+            .stats
+            .0
+            .recv_waits
+            .time(|| self.receiver.recv_timeout(timeout));
+        if result.is_ok() {
+            self.stats.0.received(self.receiver.len());
+        }
+        result
+    }
+
+    /// Receive until every sender is gone
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.recv().ok())
+    }
+
+    /// The counters, without keeping the channel alive
+    pub fn stats(&self) -> ChannelStats {
+        self.stats.clone()
+    }
+
+    pub fn snapshot(&self) -> ChannelSnapshot {
+        self.stats.snapshot_with(self.receiver.len())
+    }
+
+    /// The underlying receiver, eg. for `select!`. Nothing received on it is counted.
+    pub fn inner(&self) -> &Receiver<T> {
+        &self.receiver
+    }
+}
+
+impl<T> Clone for InstrumentedReceiver<T> {
+    fn clone(&self) -> Self {
+        self.stats.0.receivers.fetch_add(1, Ordering::Relaxed);
+        Self {
+            receiver: self.receiver.clone(),
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+impl<T> Drop for InstrumentedReceiver<T> {
+    fn drop(&mut self) {
+        if self.stats.0.receivers.fetch_sub(1, Ordering::Relaxed) == 1 {
+            self.stats.0.disconnected(Side::Receivers);
+        }
+    }
+}
+
+impl<T> fmt::Debug for InstrumentedReceiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("InstrumentedReceiver")
+            .field(&self.snapshot())
+            .finish()
+    }
+}
+
+/// A handle on the counters of a channel, that doesn't keep the channel itself alive
+#[derive(Clone)]
+pub struct ChannelStats(Arc<Counters>);
+
+impl ChannelStats {
+    /// The counters as they are, with the length of the channel as it was the last time one of
+    /// its ends was used. Snapshots taken through an end have the current one.
+    pub fn snapshot(&self) -> ChannelSnapshot {
+        self.snapshot_with(self.0.len.load(Ordering::Relaxed))
+    }
+
+    fn snapshot_with(&self, len: usize) -> ChannelSnapshot {
+        let counters = &self.0;
+        counters.len.store(len, Ordering::Relaxed);
+        let sent = counters.sent.load(Ordering::Relaxed);
+        let received = counters.received.load(Ordering::Relaxed);
+        let disconnects = counters.disconnects.lock().unwrap();
+        let gone = |side| {
+            disconnects
+                .iter()
+                .find(|(s, _)| *s == side)
+                .map(|(_, at)| at.duration_since(counters.created))
+        };
+        ChannelSnapshot {
+            name: counters.name.clone(),
+            age: counters.created.elapsed(),
+            len,
+            capacity: counters.capacity,
+            sent,
+            received,
+            failed_sends: counters.failed_sends.load(Ordering::Relaxed),
+            send_blocked: counters.send_waits.total(),
+            recv_blocked: counters.recv_waits.total(),
+            waiting_senders: counters.send_waits.waiting(),
+            waiting_receivers: counters.recv_waits.waiting(),
+            senders: counters.senders.load(Ordering::Relaxed),
+            receivers: counters.receivers.load(Ordering::Relaxed),
+            senders_gone_after: gone(Side::Senders),
+            receivers_gone_after: gone(Side::Receivers),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Senders,
+    Receivers,
+}
+
+struct Counters {
+    name: String,
+    capacity: Option<usize>,
+    created: Instant,
+    /// The length of the channel, the last time an end looked
+    len: AtomicUsize,
+    sent: AtomicU64,
+    received: AtomicU64,
+    /// Sends that found every receiver gone
+    failed_sends: AtomicU64,
+    send_waits: Waits,
+    recv_waits: Waits,
+    /// Live handles, to notice the last one going
+    senders: AtomicUsize,
+    receivers: AtomicUsize,
+    disconnects: Mutex<Vec<(Side, Instant)>>,
+}
+
+impl Counters {
+    fn new<T>(name: &str, sender: &Sender<T>) -> Self {
+        Self {
+            name: name.to_string(),
+            capacity: sender.capacity(),
+            created: Instant::now(),
+            len: AtomicUsize::new(sender.len()),
+            sent: AtomicU64::new(0),
+            received: AtomicU64::new(0),
+            failed_sends: AtomicU64::new(0),
+            send_waits: Waits::default(),
+            recv_waits: Waits::default(),
+            senders: AtomicUsize::new(1),
+            receivers: AtomicUsize::new(1),
+            disconnects: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Count an item sent, leaving the channel `len` long
+    fn sent(&self, len: usize) {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+        self.len.store(len, Ordering::Relaxed);
+    }
+
+    /// Count an item received, leaving the channel `len` long
+    fn received(&self, len: usize) {
+        self.received.fetch_add(1, Ordering::Relaxed);
+        self.len.store(len, Ordering::Relaxed);
+    }
+
+    fn disconnected(&self, side: Side) {
+        self.disconnects
+            .lock()
+            .unwrap()
+            .push((side, Instant::now()));
+    }
+}
+
+/// Time spent blocked on one end of a channel
+#[derive(Default)]
+struct Waits {
+    /// How many are blocked right now
+    waiting: AtomicUsize,
+    total_nanos: AtomicU64,
+}
+
+impl Waits {
+    fn time<R>(&self, wait: impl FnOnce() -> R) -> R {
+        self.waiting.fetch_add(1, Ordering::Relaxed);
+        let started = Instant::now();
+        let result = wait();
+        let nanos = u64::try_from(started.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        self.total_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.waiting.fetch_sub(1, Ordering::Relaxed);
+        result
+    }
+
+    fn waiting(&self) -> usize {
+        self.waiting.load(Ordering::Relaxed)
+    }
+
+    fn total(&self) -> Duration {
+        Duration::from_nanos(self.total_nanos.load(Ordering::Relaxed))
+    }
+}
+
+/// The counters of a channel at one point in time
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelSnapshot {
+    pub name: String,
+    /// Since the channel was instrumented
+    pub age: Duration,
+    /// Items waiting in the channel, see `ChannelStats::snapshot` for how current it is
+    pub len: usize,
+    /// `None` for unbounded channels
+    pub capacity: Option<usize>,
+    pub sent: u64,
+    pub received: u64,
+    pub failed_sends: u64,
+    /// Time spent waiting for room to send, added up over every sender
+    pub send_blocked: Duration,
+    /// Time spent waiting for something to receive, added up over every receiver
+    pub recv_blocked: Duration,
+    /// Senders blocked on a full channel right now
+    pub waiting_senders: usize,
+    /// Receivers blocked on an empty channel right now
+    pub waiting_receivers: usize,
+    pub senders: usize,
+    pub receivers: usize,
+    /// When the last sender was dropped, counted from `age`
+    pub senders_gone_after: Option<Duration>,
+    /// When the last receiver was dropped, counted from `age`
+    pub receivers_gone_after: Option<Duration>,
+}
+
+impl ChannelSnapshot {
+    pub fn is_full(&self) -> bool {
+        self.capacity.is_some_and(|capacity| self.len >= capacity)
+    }
+
+    /// Full, with senders waiting and nobody taking anything off. In a pipeline, the stage
+    /// reading from this channel is the one holding everything up.
+    pub fn is_stalled(&self) -> bool {
+        self.is_full() && self.waiting_senders > 0 && self.waiting_receivers == 0
+    }
+}
+
+impl fmt::Display for ChannelSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let capacity = match self.capacity {
+            Some(capacity) => capacity.to_string(),
+            None => "∞".to_string(),
+        };
+        write!(
+            f,
+            "{}: {}/{capacity} queued, {} sent, {} received, blocked {:.1?} sending ({} now) and \
+             {:.1?} receiving ({} now)",
+            self.name,
+            self.len,
+            self.sent,
+            self.received,
+            self.send_blocked,
+            self.waiting_senders,
+            self.recv_blocked,
+            self.waiting_receivers,
+        )?;
+        if self.failed_sends > 0 {
+            write!(f, ", {} sent after disconnecting", self.failed_sends)?;
+        }
+        if let Some(after) = self.senders_gone_after {
+            write!(f, ", senders gone after {after:.1?}")?;
+        }
+        if let Some(after) = self.receivers_gone_after {
+            write!(f, ", receivers gone after {after:.1?}")?;
+        }
+        if self.is_stalled() {
+            f.write_str(" (stalled)")?;
+        }
+        Ok(())
+    }
+}
+
+/// A thread snapshotting a set of channels every so often and handing the snapshots to a
+/// callback, until it is stopped or dropped
+pub struct Reporter {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Reporter {
+    pub fn start(
+        interval: Duration,
+        channels: Vec<ChannelStats>,
+        mut report: impl FnMut(&[ChannelSnapshot]) + Send + 'static,
+    ) -> Self {
+        let (stop, stopped) = crossbeam_channel::bounded::<()>(0);
+        let thread = thread::Builder::new()
+            .name("channel-reporter".to_string())
+            .spawn(move || {
+                let ticks = tick(interval);
+                loop {
+                    select! {
+                        recv(ticks) -> _ => {
+                            let snapshots: Vec<_> = channels.iter().map(|c| c.snapshot()).collect();
+                            report(&snapshots);
+                        }
+                        // Only ever disconnects, when the reporter is stopped
+                        recv(stopped) -> _ => return,
+                    }
+                }
+            })
+            .expect("failed to spawn the reporter thread");
+        Self {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// `start`, printing every snapshot to stderr
+    pub fn print(interval: Duration, channels: Vec<ChannelStats>) -> Self {
+        Self::start(interval, channels, |snapshots| {
+            for snapshot in snapshots {
+                eprintln!("{snapshot}");
+            }
+        })
+    }
+
+    /// Stop reporting, and wait for a report that is under way to finish
+    pub fn stop(mut self) {
+        self.join();
+    }
+
+    fn join(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Reporter {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_counts_sends_and_receives() {
+        let (sender, receiver) = bounded("numbers", 4);
+        for n in 0..3 {
+            sender.send(n).unwrap();
+        }
+        assert_eq!(receiver.recv(), Ok(0));
+        assert!(sender.try_send(3).is_ok());
+
+        let snapshot = sender.snapshot();
+        assert_eq!(
+            (
+                snapshot.sent,
+                snapshot.received,
+                snapshot.len,
+                snapshot.capacity
+            ),
+            (4, 1, 3, Some(4))
+        );
+        assert_eq!((snapshot.senders, snapshot.receivers), (1, 1));
+        assert_eq!(receiver.snapshot().len, 3);
+        assert!(!snapshot.is_full());
+
+        let (sender, _receiver) = unbounded::<()>("unbounded");
+        assert_eq!(sender.snapshot().capacity, None);
+    }
+
+    #[test]
+    fn it_asks_the_channel_how_long_it_is() {
+        // Items already in the channel, and ones that go around the wrappers, are still in it
+        let (raw_sender, raw_receiver) = crossbeam_channel::bounded(4);
+        raw_sender.send(0).unwrap();
+        let (sender, receiver) = instrument("wrapped", raw_sender, raw_receiver);
+        assert_eq!(receiver.stats().snapshot().len, 1);
+        sender.inner().send(1).unwrap();
+        sender.send(2).unwrap();
+
+        let snapshot = receiver.snapshot();
+        assert_eq!((snapshot.sent, snapshot.received, snapshot.len), (1, 0, 3));
+        assert_eq!(receiver.inner().recv(), Ok(0));
+        assert_eq!(sender.snapshot().len, 2);
+        assert_eq!(receiver.stats().snapshot().len, 2);
+    }
+
+    #[test]
+    fn it_spots_stalled_channels() {
+        let (sender, receiver) = bounded("stuck", 1);
+        let stats = receiver.stats();
+        sender.send(1).unwrap();
+        let blocked = thread::spawn(move || sender.send(2));
+
+        // Nobody is receiving, so the second send waits until we do
+        let patience = Instant::now();
+        while !stats.snapshot().is_stalled() {
+            assert!(patience.elapsed() < Duration::from_secs(1), "never stalled");
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(stats.snapshot().to_string().ends_with("(stalled)"));
+
+        assert_eq!(receiver.iter().collect::<Vec<_>>(), [1, 2]);
+        blocked.join().unwrap().unwrap();
+        let snapshot = stats.snapshot();
+        assert!(snapshot.send_blocked > Duration::ZERO);
+        assert_eq!(snapshot.waiting_senders, 0);
+    }
+
+    #[test]
+    fn it_records_disconnects() {
+        let (sender, receiver) = bounded("short lived", 1);
+        let stats = sender.stats();
+        let second = receiver.clone();
+        assert_eq!(stats.snapshot().receivers, 2);
+
+        drop(receiver);
+        assert_eq!(stats.snapshot().receivers_gone_after, None);
+        drop(second);
+        assert!(stats.snapshot().receivers_gone_after.is_some());
+
+        assert!(sender.send(1).is_err());
+        assert!(sender.try_send(2).is_err());
+        drop(sender);
+        let snapshot = stats.snapshot();
+        assert_eq!((snapshot.failed_sends, snapshot.sent), (2, 0));
+        assert!(snapshot.senders_gone_after.is_some());
+    }
+
+    #[test]
+    fn it_reports_periodically() {
+        let (sender, _receiver) = bounded("reported", 2);
+        sender.send(()).unwrap();
+        let (reports, reported) = crossbeam_channel::unbounded();
+        let reporter = Reporter::start(Duration::from_millis(5), vec![sender.stats()], {
+            move |snapshots| {
+                let _ = reports.send(snapshots.to_vec());
+            }
+        });
+        for _ in 0..3 {
+            let snapshots = reported.recv_timeout(Duration::from_secs(1)).unwrap();
+            assert_eq!(snapshots.len(), 1);
+            assert_eq!(
+                (snapshots[0].name.as_str(), snapshots[0].len),
+                ("reported", 1)
+            );
+        }
+        reporter.stop();
+        // The callback went with the thread, so nothing else is coming
+        while reported.try_recv().is_ok() {}
+        assert!(reported.recv().is_err());
+    }
+}
@@ -0,0 +1,24 @@
+//! Building blocks on top of `crossbeam_channel`.
+//!
+//! [`WorkerPool`] runs jobs on a fixed number of threads fed by a shared channel.
+//!
+//! [`Pipeline`] chains stages on their own threads through bounded channels, so a slow stage
+//! holds back the ones before it instead of letting work pile up.
+//!
+//! [`mux`] has the `select!` based helpers: merging receivers, debouncing, rate limiting, and
+//! requests that give up after a deadline.
+//!
+//! [`instrument`] wraps both ends of a channel to count what goes through it and how long each
+//! end spends waiting, to find the stage a bounded pipeline is stuck on.
+
+pub mod instrument;
+pub mod mux;
+pub mod pipeline;
+pub mod pool;
+
+pub use instrument::{
+    ChannelSnapshot, ChannelStats, InstrumentedReceiver, InstrumentedSender, Reporter,
+};
+pub use mux::{RateLimiter, Request, RequestError};
+pub use pipeline::{Pipeline, PipelineStats, StageStats};
+pub use pool::{JobError, JobHandle, WorkerPool};
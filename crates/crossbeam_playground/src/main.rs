@@ -1,4 +1,7 @@
+use std::{thread, time::Duration};
+
 use crossbeam_channel::{Receiver, Sender};
+use crossbeam_playground::{Pipeline, Reporter, WorkerPool, instrument};
 
 fn print_sender<T>(sender: Sender<T>) {
     println!("sender = {sender:?}");
@@ -14,4 +17,39 @@ fn main() {
 
     print_sender(b_sender);
     print_sender(ub_sender);
+
+    let pool = WorkerPool::new(4, |n: u64| (1..=n).product::<u64>());
+    for (n, result) in pool.run_all(0..10).into_iter().enumerate() {
+        println!("{n}! = {}", result.unwrap());
+    }
+    pool.shutdown();
+
+    let stats = Pipeline::from_iter("numbers", 8, 1..=10_000u64)
+        .fan_out("collatz", 4, |n| {
+            std::iter::successors(Some(n), |&n| match n {
+                1 => None,
+                n if n % 2 == 0 => Some(n / 2),
+                n => Some(3 * n + 1),
+            })
+            .count()
+        })
+        .filter("long", |steps| *steps > 100)
+        .batch("batch", 100)
+        .run(|batch| println!("{} numbers take over 100 steps", batch.len()));
+    println!("{stats}");
+
+    // A producer that outpaces its consumer, to watch the channel fill up and stall
+    let (sender, receiver) = instrument::bounded("slow consumer", 4);
+    let reporter = Reporter::print(Duration::from_millis(10), vec![sender.stats()]);
+    let producer = thread::spawn(move || {
+        for n in 0..20 {
+            sender.send(n).unwrap();
+        }
+    });
+    for _ in receiver.iter() {
+        thread::sleep(Duration::from_millis(3));
+    }
+    producer.join().unwrap();
+    reporter.stop();
+    println!("receiver = {receiver:?}");
 }
@@ -0,0 +1,279 @@
+use std::{
+    fmt, thread,
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{Receiver, Select, Sender, after, never, select, tick};
+
+/// One channel with everything sent on any of `inputs`, in whatever order it arrives. It
+/// disconnects once all of them have.
+pub fn merge<T: Send + 'static>(inputs: Vec<Receiver<T>>) -> Receiver<T> {
+    let (sender, receiver) = crossbeam_channel::bounded(inputs.len().max(1));
+    spawn("merge", move || {
+        let mut select = Select::new();
+        for input in &inputs {
+            select.recv(input);
+        }
+        let mut open = inputs.len();
+        while open > 0 {
+            let op = select.select();
+            let index = op.index();
+            match op.recv(&inputs[index]) {
+                Ok(item) => {
+                    if sender.send(item).is_err() {
+                        return;
+                    }
+                }
+                // Disconnected inputs are always ready, so stop selecting on them
+                Err(_) => {
+                    select.remove(index);
+                    open -= 1;
+                }
+            }
+        }
+    });
+    receiver
+}
+
+/// Only pass on an item once `quiet` has gone by without another one coming after it, eg. to
+/// act on a burst of file change events once it is over. When `input` disconnects, whatever
+/// was still waiting is sent straight away.
+pub fn debounce<T: Send + 'static>(input: Receiver<T>, quiet: Duration) -> Receiver<T> {
+    let (sender, receiver) = crossbeam_channel::bounded(1);
+    spawn("debounce", move || {
+        let mut pending = None;
+        // A fresh `after` for every item restarts the wait, and `never` stands in while there
+        // is nothing to wait for
+        let mut deadline = never();
+        loop {
+            select! {
+                recv(input) -> item => match item {
+                    Ok(item) => {
+                        pending = Some(item);
+                        deadline = after(quiet);
+                    }
+                    Err(_) => {
+                        if let Some(item) = pending {
+                            let _ = sender.send(item);
+                        }
+                        return;
+                    }
+                },
+                recv(deadline) -> _ => {
+                    // `select!` picks at random when both are ready, and an item that came in
+                    // just as the time ran out still counts
+                    if !input.is_empty() {
+                        continue;
+                    }
+                    deadline = never();
+                    let item = pending.take().expect("only waiting with an item pending");
+                    if sender.send(item).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+    receiver
+}
+
+/// Lets callers through at most once per interval, however many are waiting. An interval
+/// nobody used is not saved up, so a burst after a quiet spell gets one caller through
+/// straight away and the rest at the usual pace.
+pub struct RateLimiter {
+    ticks: Receiver<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            ticks: tick(interval),
+        }
+    }
+
+    /// Block until it is our turn
+    pub fn wait(&self) {
+        self.ticks.recv().expect("tickers never disconnect");
+    }
+
+    /// Take a turn if there is one now, without waiting
+    pub fn try_wait(&self) -> bool {
+        self.ticks.try_recv().is_ok()
+    }
+}
+
+/// `input`, slowed down to at most one item per `interval`
+pub fn rate_limit<T: Send + 'static>(input: Receiver<T>, interval: Duration) -> Receiver<T> {
+    let (sender, receiver) = crossbeam_channel::bounded(0);
+    spawn("rate-limit", move || {
+        let limiter = RateLimiter::new(interval);
+        for item in input {
+            limiter.wait();
+            if sender.send(item).is_err() {
+                return;
+            }
+        }
+    });
+    receiver
+}
+
+/// A request sent with `request`, for whoever handles it to `respond` to
+pub struct Request<Q, A> {
+    pub body: Q,
+    reply: Sender<A>,
+}
+
+impl<Q, A> Request<Q, A> {
+    /// Send the answer back. Returns `false` if the caller gave up on it already.
+    pub fn respond(self, answer: A) -> bool {
+        self.reply.send(answer).is_ok()
+    }
+}
+
+/// Why `request` didn't get an answer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestError {
+    /// The deadline passed, either waiting for room to send the request or for the answer
+    Timeout,
+    /// Nobody is handling requests anymore, or the request was dropped without an answer
+    Disconnected,
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout => f.write_str("request timed out"),
+            Self::Disconnected => f.write_str("request was not answered"),
+        }
+    }
+}
+
+impl std::error::Error for RequestError {}
+
+/// Send `body` to whoever handles `requests` and wait for the answer, giving up after
+/// `timeout` in total
+pub fn request<Q, A>(
+    requests: &Sender<Request<Q, A>>,
+    body: Q,
+    timeout: Duration,
+) -> Result<A, RequestError> {
+    let deadline = after(timeout);
+    let (reply, answer) = crossbeam_channel::bounded(1);
+    select! {
+        send(requests, Request { body, reply }) -> sent => {
+            sent.map_err(|_| RequestError::Disconnected)?;
+        }
+        recv(deadline) -> _ => return Err(RequestError::Timeout),
+    }
+    select! {
+        recv(answer) -> answer => answer.map_err(|_| RequestError::Disconnected),
+        recv(deadline) -> _ => Err(RequestError::Timeout),
+    }
+}
+
+fn spawn(name: &str, work: impl FnOnce() + Send + 'static) {
+    thread::Builder::new()
+        .name(name.to_string())
+        .spawn(work)
+        .expect("failed to spawn a forwarding thread");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATIENCE: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn it_merges_receivers() {
+        let (senders, receivers): (Vec<_>, Vec<_>) =
+            (0..3).map(|_| crossbeam_channel::unbounded()).unzip();
+        let merged = merge(receivers);
+        for (i, sender) in senders.into_iter().enumerate() {
+            thread::spawn(move || {
+                for n in 0..10 {
+                    sender.send(i * 10 + n).unwrap();
+                }
+            });
+        }
+        // Ends once every sender is gone
+        let mut items: Vec<_> = merged.iter().collect();
+        items.sort();
+        assert_eq!(items, (0..30).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn it_does_not_wait_on_quiet_receivers() {
+        let (_quiet, never_sends) = crossbeam_channel::unbounded::<u32>();
+        let (busy, sends) = crossbeam_channel::unbounded();
+        let merged = merge(vec![never_sends, sends]);
+        busy.send(1).unwrap();
+        assert_eq!(merged.recv_timeout(PATIENCE), Ok(1));
+    }
+
+    #[test]
+    fn it_debounces_bursts() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let debounced = debounce(receiver, Duration::from_millis(50));
+        for n in 1..=3 {
+            sender.send(n).unwrap();
+        }
+        assert_eq!(debounced.recv_timeout(PATIENCE), Ok(3));
+        assert!(debounced.try_recv().is_err());
+
+        // Whatever is pending goes out when the sender does
+        sender.send(4).unwrap();
+        sender.send(5).unwrap();
+        drop(sender);
+        assert_eq!(debounced.recv_timeout(PATIENCE), Ok(5));
+        assert!(debounced.recv_timeout(PATIENCE).is_err());
+    }
+
+    #[test]
+    fn it_limits_the_rate() {
+        let interval = Duration::from_millis(10);
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        for n in 0..5 {
+            sender.send(n).unwrap();
+        }
+        drop(sender);
+
+        let started = Instant::now();
+        let items: Vec<_> = rate_limit(receiver, interval).iter().collect();
+        assert_eq!(items, [0, 1, 2, 3, 4]);
+        assert!(started.elapsed() >= interval * 5, "{:?}", started.elapsed());
+
+        let limiter = RateLimiter::new(Duration::from_secs(60));
+        assert!(!limiter.try_wait());
+    }
+
+    #[test]
+    fn it_answers_requests_before_the_deadline() {
+        let (requests, handler) = crossbeam_channel::unbounded::<Request<u32, u32>>();
+        thread::spawn(move || {
+            for request in handler {
+                let answer = request.body * 2;
+                request.respond(answer);
+            }
+        });
+        assert_eq!(request(&requests, 21, PATIENCE), Ok(42));
+    }
+
+    #[test]
+    fn it_times_out_requests() {
+        // Queued, but nobody ever takes it off the queue to answer it
+        let (requests, handler) = crossbeam_channel::unbounded::<Request<(), ()>>();
+        let timeout = Duration::from_millis(20);
+        assert_eq!(request(&requests, (), timeout), Err(RequestError::Timeout));
+
+        // Never even taken, with no room to queue it
+        let (full, _handler) = crossbeam_channel::bounded::<Request<(), ()>>(0);
+        assert_eq!(request(&full, (), timeout), Err(RequestError::Timeout));
+
+        drop(handler);
+        assert_eq!(
+            request(&requests, (), timeout),
+            Err(RequestError::Disconnected)
+        );
+    }
+}
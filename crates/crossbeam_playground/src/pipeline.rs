@@ -0,0 +1,532 @@
+use std::{
+    fmt, panic,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+
+/// How many items fit between two stages unless `capacity` says otherwise
+pub const DEFAULT_CAPACITY: usize = 16;
+
+/// A chain of stages, each running on its own thread(s) and connected to the next by a bounded
+/// crossbeam channel. A stage that can't keep up makes the channel in front of it fill up, which
+/// blocks the stage before it, and so on back to the source, so nothing ever queues up more than
+/// the channels have room for.
+///
+/// Nothing runs until `run` (or `collect`) is called.
+pub struct Pipeline<T> {
+    output: Receiver<T>,
+    capacity: usize,
+    stages: Vec<Stage>,
+}
+
+/// A stage that hasn't been started yet
+struct Stage {
+    counters: Arc<Counters>,
+    workers: Vec<Box<dyn FnOnce() + Send>>,
+}
+
+impl<T: Send + 'static> Pipeline<T> {
+    /// Start with a stage that sends every item of `items` down the pipeline
+    pub fn from_iter<I>(name: &str, capacity: usize, items: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Send + 'static,
+    {
+        let (sender, receiver) = crossbeam_channel::bounded(capacity);
+        let counters = Arc::new(Counters::new(name, 1, 0));
+        let output = Output {
+            sender,
+            counters: Arc::clone(&counters),
+        };
+        let items = items.into_iter();
+        let work = move || {
+            for item in items {
+                if !output.send(item) {
+                    break;
+                }
+            }
+            output.counters.finish();
+        };
+        Self {
+            output: receiver,
+            capacity,
+            stages: vec![Stage {
+                counters,
+                workers: vec![Box::new(work)],
+            }],
+        }
+    }
+
+    /// Make the channels of the stages added from here on hold `capacity` items
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Turn every item into `f(item)`
+    pub fn map<O: Send + 'static>(
+        self,
+        name: &str,
+        f: impl Fn(T) -> O + Send + Sync + 'static,
+    ) -> Pipeline<O> {
+        self.fan_out(name, 1, f)
+    }
+
+    /// Only pass on the items `keep` says yes to
+    pub fn filter(self, name: &str, keep: impl Fn(&T) -> bool + Send + Sync + 'static) -> Self {
+        self.stage(name, 1, move |input, output| {
+            while let Some(item) = input.recv() {
+                if keep(&item) && !output.send(item) {
+                    break;
+                }
+            }
+        })
+    }
+
+    /// Group items into batches of `size`. The last batch holds whatever is left, if anything.
+    pub fn batch(self, name: &str, size: usize) -> Pipeline<Vec<T>> {
+        assert!(size > 0, "batches need room for at least one item");
+        self.stage(name, 1, move |input, output| {
+            let mut batch = Vec::with_capacity(size);
+            while let Some(item) = input.recv() {
+                batch.push(item);
+                if batch.len() == size
+                    && !output.send(std::mem::replace(&mut batch, Vec::with_capacity(size)))
+                {
+                    return;
+                }
+            }
+            if !batch.is_empty() {
+                output.send(batch);
+            }
+        })
+    }
+
+    /// `map`, but on `workers` threads taking items from the same channel, for stages that are
+    /// slower than the rest. Items can come out in a different order than they went in.
+    pub fn fan_out<O: Send + 'static>(
+        self,
+        name: &str,
+        workers: usize,
+        f: impl Fn(T) -> O + Send + Sync + 'static,
+    ) -> Pipeline<O> {
+        self.stage(name, workers, move |input, output| {
+            while let Some(item) = input.recv() {
+                if !output.send(f(item)) {
+                    break;
+                }
+            }
+        })
+    }
+
+    /// Merge the output of several pipelines into one, in whatever order items arrive. The
+    /// merged pipeline runs all of their stages.
+    pub fn fan_in(name: &str, capacity: usize, pipelines: Vec<Pipeline<T>>) -> Self {
+        let (sender, receiver) = crossbeam_channel::bounded(capacity);
+        // Each worker measures the queue in front of it, so the largest of them is the most
+        // any of those measurements can show
+        let input_capacity = pipelines.iter().map(|p| room(&p.output)).max();
+        let counters = Arc::new(Counters::new(
+            name,
+            pipelines.len(),
+            input_capacity.unwrap_or(0),
+        ));
+        let mut stages = Vec::new();
+        let mut workers: Vec<Box<dyn FnOnce() + Send>> = Vec::new();
+        for pipeline in pipelines {
+            stages.extend(pipeline.stages);
+            let input = Input {
+                receiver: pipeline.output,
+                counters: Arc::clone(&counters),
+            };
+            let output = Output {
+                sender: sender.clone(),
+                counters: Arc::clone(&counters),
+            };
+            workers.push(Box::new(move || {
+                while let Some(item) = input.recv() {
+                    if !output.send(item) {
+                        break;
+                    }
+                }
+                output.counters.finish();
+            }));
+        }
+        stages.push(Stage { counters, workers });
+        Self {
+            output: receiver,
+            capacity,
+            stages,
+        }
+    }
+
+    /// Add a stage of `workers` threads, each running `work` until it returns
+    fn stage<O: Send + 'static>(
+        mut self,
+        name: &str,
+        workers: usize,
+        work: impl Fn(&Input<T>, &Output<O>) + Send + Sync + 'static,
+    ) -> Pipeline<O> {
+        assert!(workers > 0, "a stage needs at least one worker");
+        let (sender, receiver) = crossbeam_channel::bounded(self.capacity);
+        let counters = Arc::new(Counters::new(name, workers, room(&self.output)));
+        let work = Arc::new(work);
+        let workers = (0..workers)
+            .map(|_| {
+                let input = Input {
+                    receiver: self.output.clone(),
+                    counters: Arc::clone(&counters),
+                };
+                let output = Output {
+                    sender: sender.clone(),
+                    counters: Arc::clone(&counters),
+                };
+                let work = Arc::clone(&work);
+                Box::new(move || {
+                    work(&input, &output);
+                    output.counters.finish();
+                }) as Box<dyn FnOnce() + Send>
+            })
+            .collect();
+        self.stages.push(Stage { counters, workers });
+        Pipeline {
+            output: receiver,
+            capacity: self.capacity,
+            stages: self.stages,
+        }
+    }
+
+    /// Start every stage and hand each item that comes out of the last one to `sink`, on this
+    /// thread. Returns once everything has gone through. A panic in any stage is passed on.
+    pub fn run(self, mut sink: impl FnMut(T)) -> PipelineStats {
+        let started = Instant::now();
+        let mut counters = Vec::new();
+        let mut threads: Vec<JoinHandle<()>> = Vec::new();
+        for stage in self.stages {
+            for (i, work) in stage.workers.into_iter().enumerate() {
+                let thread = thread::Builder::new()
+                    .name(format!("{}-{i}", stage.counters.name))
+                    .spawn(work)
+                    .expect("failed to spawn a stage thread");
+                threads.push(thread);
+            }
+            counters.push(stage.counters);
+        }
+
+        let input = Input {
+            counters: Arc::new(Counters::new("sink", 1, room(&self.output))),
+            receiver: self.output,
+        };
+        while let Some(item) = input.recv() {
+            sink(item);
+        }
+        input.counters.finish();
+        counters.push(input.counters);
+
+        for thread in threads {
+            if let Err(payload) = thread.join() {
+                panic::resume_unwind(payload);
+            }
+        }
+        PipelineStats {
+            elapsed: started.elapsed(),
+            stages: counters.iter().map(|c| c.stats(started)).collect(),
+        }
+    }
+
+    /// `run`, collecting every item that comes out
+    pub fn collect(self) -> (Vec<T>, PipelineStats) {
+        let mut items = Vec::new();
+        let stats = self.run(|item| items.push(item));
+        (items, stats)
+    }
+}
+
+/// How many items the channel `receiver` is on holds
+fn room<T>(receiver: &Receiver<T>) -> usize {
+    receiver.capacity().expect("pipeline channels are bounded")
+}
+
+/// The receiving end of the channel in front of a stage
+struct Input<T> {
+    receiver: Receiver<T>,
+    counters: Arc<Counters>,
+}
+
+impl<T> Input<T> {
+    /// The next item, or `None` once the stage before has finished
+    fn recv(&self) -> Option<T> {
+        let depth = self.receiver.len();
+        let item = self.receiver.recv().ok()?;
+        self.counters.received(depth);
+        Some(item)
+    }
+}
+
+/// The sending end of the channel behind a stage
+struct Output<T> {
+    sender: Sender<T>,
+    counters: Arc<Counters>,
+}
+
+impl<T> Output<T> {
+    /// Send `item` on, waiting for room if the channel is full. `false` if nobody is listening
+    /// anymore, in which case the stage should stop.
+    fn send(&self, item: T) -> bool {
+        let sent = match self.sender.try_send(item) {
+            Ok(()) => true,
+            Err(TrySendError::Full(item)) => {
+                let blocked = Instant::now();
+                let sent = self.sender.send(item).is_ok();
+                self.counters.blocked(blocked.elapsed());
+                sent
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        };
+        if sent {
+            self.counters.sent.fetch_add(1, Ordering::Relaxed);
+        }
+        sent
+    }
+}
+
+/// What the workers of a stage count while running
+struct Counters {
+    name: String,
+    workers: usize,
+    capacity: usize,
+    received: AtomicU64,
+    sent: AtomicU64,
+    max_queue_depth: AtomicUsize,
+    total_queue_depth: AtomicU64,
+    blocked_nanos: AtomicU64,
+    /// When the last worker to finish did
+    finished: Mutex<Option<Instant>>,
+}
+
+impl Counters {
+    fn new(name: &str, workers: usize, capacity: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            workers,
+            capacity,
+            received: AtomicU64::new(0),
+            sent: AtomicU64::new(0),
+            max_queue_depth: AtomicUsize::new(0),
+            total_queue_depth: AtomicU64::new(0),
+            blocked_nanos: AtomicU64::new(0),
+            finished: Mutex::new(None),
+        }
+    }
+
+    /// An item was taken off the input, which had `depth` items in it at the time
+    fn received(&self, depth: usize) {
+        self.received.fetch_add(1, Ordering::Relaxed);
+        self.max_queue_depth.fetch_max(depth, Ordering::Relaxed);
+        self.total_queue_depth
+            .fetch_add(depth as u64, Ordering::Relaxed);
+    }
+
+    fn blocked(&self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.blocked_nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    fn finish(&self) {
+        *self.finished.lock().unwrap() = Some(Instant::now());
+    }
+
+    fn stats(&self, started: Instant) -> StageStats {
+        let received = self.received.load(Ordering::Relaxed);
+        let total_depth = self.total_queue_depth.load(Ordering::Relaxed);
+        let finished = self.finished.lock().unwrap().unwrap_or_else(Instant::now);
+        StageStats {
+            name: self.name.clone(),
+            workers: self.workers,
+            received,
+            sent: self.sent.load(Ordering::Relaxed),
+            elapsed: finished.duration_since(started),
+            capacity: self.capacity,
+            max_queue_depth: self.max_queue_depth.load(Ordering::Relaxed),
+            mean_queue_depth: match received {
+                0 => 0.0,
+                n => total_depth as f64 / n as f64,
+            },
+            blocked: Duration::from_nanos(self.blocked_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// How a pipeline run went, stage by stage
+#[derive(Debug, Clone)]
+pub struct PipelineStats {
+    /// In the order the stages were added, ending with the sink
+    pub stages: Vec<StageStats>,
+    pub elapsed: Duration,
+}
+
+impl PipelineStats {
+    pub fn stage(&self, name: &str) -> Option<&StageStats> {
+        self.stages.iter().find(|stage| stage.name == name)
+    }
+}
+
+impl fmt::Display for PipelineStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<12} {:>7} {:>9} {:>9} {:>12} {:>11} {:>10}",
+            "stage", "workers", "received", "sent", "items/s", "queue max", "blocked"
+        )?;
+        for stage in &self.stages {
+            writeln!(
+                f,
+                "{:<12} {:>7} {:>9} {:>9} {:>12.0} {:>5}/{:<5} {:>10.1?}",
+                stage.name,
+                stage.workers,
+                stage.received,
+                stage.sent,
+                stage.throughput(),
+                stage.max_queue_depth,
+                stage.capacity,
+                stage.blocked
+            )?;
+        }
+        write!(f, "took {:.1?}", self.elapsed)
+    }
+}
+
+/// How one stage did
+#[derive(Debug, Clone)]
+pub struct StageStats {
+    pub name: String,
+    pub workers: usize,
+    /// Items taken off the channel in front of the stage
+    pub received: u64,
+    /// Items sent on to the next stage
+    pub sent: u64,
+    /// From the start of the run until the last worker of the stage finished
+    pub elapsed: Duration,
+    /// Room in the channel in front of the stage, 0 for the source. A `fan_in` stage has one
+    /// channel per pipeline it merges, and this is the largest of them.
+    pub capacity: usize,
+    /// The most items that were waiting in front of the stage when it took the next one
+    pub max_queue_depth: usize,
+    pub mean_queue_depth: f64,
+    /// Time spent waiting for room in the next channel, added up over the workers. A stage that
+    /// blocks a lot is being held back by the ones after it.
+    pub blocked: Duration,
+}
+
+impl StageStats {
+    /// Items per second this stage passed on, or took in for the sink
+    pub fn throughput(&self) -> f64 {
+        let items = self.sent.max(self.received);
+        match self.elapsed.as_secs_f64() {
+            0.0 => 0.0,
+            secs => items as f64 / secs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_maps_filters_and_batches() {
+        let (batches, stats) = Pipeline::from_iter("numbers", 4, 0..100)
+            .map("double", |n: u32| n * 2)
+            .filter("thirds", |n| n % 3 == 0)
+            .batch("batch", 8)
+            .collect();
+
+        let expected: Vec<u32> = (0..100).map(|n| n * 2).filter(|n| n % 3 == 0).collect();
+        assert_eq!(batches.concat(), expected);
+        assert_eq!(batches.len(), 5);
+        assert!(batches[..4].iter().all(|batch| batch.len() == 8));
+
+        let counts: Vec<_> = stats
+            .stages
+            .iter()
+            .map(|stage| (stage.name.as_str(), stage.received, stage.sent))
+            .collect();
+        assert_eq!(
+            counts,
+            [
+                ("numbers", 0, 100),
+                ("double", 100, 100),
+                ("thirds", 100, 34),
+                ("batch", 34, 5),
+                ("sink", 5, 0),
+            ]
+        );
+        assert!(stats.stages.iter().all(|s| s.max_queue_depth <= 4));
+
+        let (_, stats) = Pipeline::from_iter("numbers", 4, 0..10)
+            .capacity(1)
+            .map("same", |n: u32| n)
+            .collect();
+        let capacities: Vec<_> = stats.stages.iter().map(|s| s.capacity).collect();
+        assert_eq!(capacities, [0, 4, 1]);
+    }
+
+    #[test]
+    fn it_fans_out_and_in() {
+        let (mut squares, stats) = Pipeline::from_iter("numbers", 8, 0..1000u64)
+            .fan_out("square", 4, |n| n * n)
+            .collect();
+        squares.sort();
+        assert_eq!(squares, (0..1000).map(|n| n * n).collect::<Vec<_>>());
+        let square = stats.stage("square").unwrap();
+        assert_eq!(
+            (square.workers, square.received, square.sent),
+            (4, 1000, 1000)
+        );
+
+        let odd = Pipeline::from_iter("odd", 2, (0..50).filter(|n| n % 2 == 1));
+        let even = Pipeline::from_iter("even", 3, (0..50).filter(|n| n % 2 == 0));
+        let (mut merged, stats) = Pipeline::fan_in("merge", 5, vec![odd, even]).collect();
+        merged.sort();
+        assert_eq!(merged, (0..50).collect::<Vec<_>>());
+        // Every stage reports the channel in front of it, not the one behind
+        let stages: Vec<_> = stats
+            .stages
+            .iter()
+            .map(|s| (s.name.as_str(), s.capacity))
+            .collect();
+        assert_eq!(stages, [("odd", 0), ("even", 0), ("merge", 3), ("sink", 5)]);
+    }
+
+    #[test]
+    fn it_holds_back_the_source_while_the_sink_is_busy() {
+        let produced = Arc::new(AtomicUsize::new(0));
+        let source = {
+            let produced = Arc::clone(&produced);
+            (0..1000).inspect(move |_| {
+                produced.fetch_add(1, Ordering::SeqCst);
+            })
+        };
+        let mut seen = None;
+        let stats = Pipeline::from_iter("numbers", 2, source)
+            .map("same", |n: i32| n)
+            .run(|n| {
+                if n == 0 {
+                    // Plenty of time for the source to run ahead, if anything let it
+                    thread::sleep(Duration::from_millis(50));
+                    seen = Some(produced.load(Ordering::SeqCst));
+                }
+            });
+
+        // Two channels of 2, one item in each stage and one in the sink
+        assert!(seen.unwrap() <= 2 * 2 + 3, "{seen:?} items produced");
+        assert_eq!(stats.stage("sink").unwrap().received, 1000);
+        assert!(stats.stage("same").unwrap().blocked > Duration::ZERO);
+    }
+}
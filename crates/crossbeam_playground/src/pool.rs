@@ -0,0 +1,246 @@
+use std::{
+    any::Any,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+    thread::{self, JoinHandle},
+};
+
+use crossbeam_channel::{Receiver, Sender};
+
+/// A fixed number of threads running jobs of type `J` into results of type `R`, all with the
+/// same handler. Jobs are queued on a crossbeam channel that every worker takes from, and each
+/// gets its own channel to send the result back on.
+///
+/// Dropping the pool (or calling `shutdown`) drops the sending side of the queue. The workers
+/// finish whatever is still queued, see the channel disconnect, and stop.
+pub struct WorkerPool<J, R> {
+    jobs: Option<Sender<Job<J, R>>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+struct Job<J, R> {
+    input: J,
+    reply: Sender<Result<R, JobError>>,
+}
+
+/// Why a job didn't produce a result
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobError {
+    /// The handler panicked, with this message. The worker carries on with the next job.
+    Panicked(String),
+    /// The job was dropped without running, which only happens if the workers are gone
+    Lost,
+}
+
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Panicked(msg) => write!(f, "job panicked: {msg}"),
+            Self::Lost => f.write_str("job was lost before it ran"),
+        }
+    }
+}
+
+impl std::error::Error for JobError {}
+
+/// The result of a submitted job, once it is there
+pub struct JobHandle<R> {
+    reply: Receiver<Result<R, JobError>>,
+}
+
+impl<R> JobHandle<R> {
+    /// Block until the job has run
+    pub fn wait(self) -> Result<R, JobError> {
+        self.reply.recv().unwrap_or(Err(JobError::Lost))
+    }
+
+    /// The channel the result comes in on, eg. to `select!` over several jobs
+    pub fn receiver(&self) -> &Receiver<Result<R, JobError>> {
+        &self.reply
+    }
+}
+
+impl<J: Send + 'static, R: Send + 'static> WorkerPool<J, R> {
+    /// Start `workers` threads running `handler`, with an unbounded queue in front of them
+    pub fn new(workers: usize, handler: impl Fn(J) -> R + Send + Sync + 'static) -> Self {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        Self::start(workers, sender, receiver, handler)
+    }
+
+    /// `new`, but with room for only `capacity` jobs in the queue. `submit` blocks while it is
+    /// full, so a producer can't get ahead of the workers by more than that.
+    pub fn bounded(
+        workers: usize,
+        capacity: usize,
+        handler: impl Fn(J) -> R + Send + Sync + 'static,
+    ) -> Self {
+        let (sender, receiver) = crossbeam_channel::bounded(capacity);
+        Self::start(workers, sender, receiver, handler)
+    }
+
+    fn start(
+        workers: usize,
+        sender: Sender<Job<J, R>>,
+        receiver: Receiver<Job<J, R>>,
+        handler: impl Fn(J) -> R + Send + Sync + 'static,
+    ) -> Self {
+        assert!(workers > 0, "a pool needs at least one worker");
+        let handler = Arc::new(handler);
+        let workers = (0..workers)
+            .map(|i| {
+                let receiver = receiver.clone();
+                let handler = Arc::clone(&handler);
+                thread::Builder::new()
+                    .name(format!("worker-{i}"))
+                    .spawn(move || work(&receiver, handler.as_ref()))
+                    .expect("failed to spawn a worker thread")
+            })
+            .collect();
+        Self {
+            jobs: Some(sender),
+            workers,
+        }
+    }
+
+    /// Queue `input` for the next free worker
+    pub fn submit(&self, input: J) -> JobHandle<R> {
+        let (reply, receiver) = crossbeam_channel::bounded(1);
+        let jobs = self.jobs.as_ref().expect("only taken when shutting down");
+        // The workers only hang up when the pool is dropped, and if they have anyway the reply
+        // sender goes with the job, which `wait` reports as `Lost`
+        let _ = jobs.send(Job { input, reply });
+        JobHandle { reply: receiver }
+    }
+
+    /// Run every input, and collect the results in the order the inputs came in
+    pub fn run_all(&self, inputs: impl IntoIterator<Item = J>) -> Vec<Result<R, JobError>> {
+        let handles: Vec<_> = inputs.into_iter().map(|input| self.submit(input)).collect();
+        handles.into_iter().map(JobHandle::wait).collect()
+    }
+
+    /// How many workers there are
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Stop taking jobs, and wait for the workers to finish the ones still queued
+    pub fn shutdown(mut self) {
+        self.join();
+    }
+}
+
+impl<J, R> WorkerPool<J, R> {
+    fn join(&mut self) {
+        // Dropping the only sender disconnects the queue once the workers have emptied it
+        drop(self.jobs.take());
+        for worker in self.workers.drain(..) {
+            // Handler panics are caught per job, so a worker only ends in a panic if sending the
+            // result back did, and there's nobody left to tell about that
+            let _ = worker.join();
+        }
+    }
+}
+
+impl<J, R> Drop for WorkerPool<J, R> {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+fn work<J, R>(jobs: &Receiver<Job<J, R>>, handler: &(impl Fn(J) -> R + ?Sized)) {
+    // `recv` only fails once the queue is empty and the pool has hung up
+    while let Ok(Job { input, reply }) = jobs.recv() {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| handler(input)))
+            .map_err(|payload| JobError::Panicked(panic_message(payload.as_ref())));
+        // Whoever submitted the job may not be waiting for it anymore, which is fine
+        let _ = reply.send(result);
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "(no message)".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Barrier, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+
+    #[test]
+    fn it_runs_every_job() {
+        let pool = WorkerPool::new(4, |n: u64| n * n);
+        let results = pool.run_all(0..100);
+        assert_eq!(results.len(), 100);
+        for (n, result) in results.into_iter().enumerate() {
+            assert_eq!(result, Ok(n as u64 * n as u64));
+        }
+    }
+
+    #[test]
+    fn it_runs_jobs_on_every_worker_at_once() {
+        // Every job waits for all the others, so this only finishes if 4 run side by side
+        let barrier = Barrier::new(4);
+        let threads = Arc::new(Mutex::new(Vec::new()));
+        let pool = WorkerPool::new(4, {
+            let threads = Arc::clone(&threads);
+            move |_: ()| {
+                barrier.wait();
+                let name = thread::current().name().unwrap().to_string();
+                threads.lock().unwrap().push(name);
+            }
+        });
+        assert_eq!(pool.size(), 4);
+        assert!(pool.run_all([(); 4]).iter().all(Result::is_ok));
+
+        let mut threads = threads.lock().unwrap().clone();
+        threads.sort();
+        assert_eq!(threads, ["worker-0", "worker-1", "worker-2", "worker-3"]);
+    }
+
+    #[test]
+    fn it_isolates_panics() {
+        let pool = WorkerPool::new(2, |n: u32| {
+            assert!(n % 10 != 3, "{n} is unlucky");
+            n
+        });
+        let results = pool.run_all(0..30);
+        let panicked: Vec<_> = results.iter().filter_map(|r| r.as_ref().err()).collect();
+        assert_eq!(
+            panicked,
+            [
+                &JobError::Panicked("3 is unlucky".to_string()),
+                &JobError::Panicked("13 is unlucky".to_string()),
+                &JobError::Panicked("23 is unlucky".to_string()),
+            ]
+        );
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 27);
+        // Both workers are still there
+        assert_eq!(pool.run_all([1, 2]), [Ok(1), Ok(2)]);
+    }
+
+    #[test]
+    fn it_finishes_queued_jobs_on_shutdown() {
+        let done = Arc::new(AtomicUsize::new(0));
+        let pool = WorkerPool::bounded(3, 10, {
+            let done = Arc::clone(&done);
+            move |_: usize| done.fetch_add(1, Ordering::SeqCst)
+        });
+        // Nobody waits for these, they still get run
+        for n in 0..50 {
+            drop(pool.submit(n));
+        }
+        pool.shutdown();
+        assert_eq!(done.load(Ordering::SeqCst), 50);
+    }
+}
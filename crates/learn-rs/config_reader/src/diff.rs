@@ -0,0 +1,197 @@
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{Config, Error, schema};
+
+/// What happened to a single value between two configs
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// One value that differs between two configs
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Change {
+    /// Dotted path to the value, eg. `database.url`
+    pub path: String,
+    pub kind: ChangeKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<Value>,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |v: &Option<Value>| v.as_ref().map(Value::to_string).unwrap_or_default();
+        match self.kind {
+            ChangeKind::Added => write!(f, "+ {}: {}", self.path, show(&self.new)),
+            ChangeKind::Removed => write!(f, "- {}: {}", self.path, show(&self.old)),
+            ChangeKind::Changed => write!(
+                f,
+                "~ {}: {} -> {}",
+                self.path,
+                show(&self.old),
+                show(&self.new)
+            ),
+        }
+    }
+}
+
+/// Compare two configs field by field. Both sides are compared as deserialized, so formatting,
+/// key order and the file format they came from don't matter. Secrets are compared, but their
+/// values are never part of the result.
+pub fn config_diff(old: &Config, new: &Config) -> Result<Vec<Change>, Error> {
+    let old = serde_json::to_value(old).map_err(Error::Value)?;
+    let new = serde_json::to_value(new).map_err(Error::Value)?;
+    let secrets = secret_paths(&schema::config_schema());
+
+    let mut changes = diff(&old, &new);
+    for change in &mut changes {
+        if secrets.contains(&change.path) {
+            let redacted = Some(Value::from("[REDACTED]"));
+            change.old = change.old.as_ref().and(redacted.clone());
+            change.new = change.new.as_ref().and(redacted);
+        }
+    }
+    Ok(changes)
+}
+
+/// Every difference between two documents. Maps are compared key by key, anything else
+/// (lists included) as a whole.
+pub fn diff(old: &Value, new: &Value) -> Vec<Change> {
+    let mut changes = Vec::new();
+    diff_at("", old, new, &mut changes);
+    changes
+}
+
+fn diff_at(path: &str, old: &Value, new: &Value, changes: &mut Vec<Change>) {
+    let (Value::Object(old), Value::Object(new)) = (old, new) else {
+        if old != new {
+            changes.push(Change {
+                path: path.to_string(),
+                kind: ChangeKind::Changed,
+                old: Some(old.clone()),
+                new: Some(new.clone()),
+            });
+        }
+        return;
+    };
+
+    let child = |key: &str| match path {
+        "" => key.to_string(),
+        _ => format!("{path}.{key}"),
+    };
+    for (key, old_value) in old {
+        match new.get(key) {
+            Some(new_value) => diff_at(&child(key), old_value, new_value, changes),
+            None => changes.push(Change {
+                path: child(key),
+                kind: ChangeKind::Removed,
+                old: Some(old_value.clone()),
+                new: None,
+            }),
+        }
+    }
+    for (key, new_value) in new {
+        if !old.contains_key(key) {
+            changes.push(Change {
+                path: child(key),
+                kind: ChangeKind::Added,
+                old: None,
+                new: Some(new_value.clone()),
+            });
+        }
+    }
+}
+
+/// Dotted paths of the properties a schema marks as `writeOnly`, which is how secrets show up
+fn secret_paths(schema: &Value) -> Vec<String> {
+    fn walk(root: &Value, schema: &Value, path: &str, found: &mut Vec<String>) {
+        let schema = match schema.get("$ref").and_then(Value::as_str) {
+            Some(reference) => match reference.strip_prefix('#').and_then(|p| root.pointer(p)) {
+                Some(target) => target,
+                None => return,
+            },
+            None => schema,
+        };
+        if schema.get("writeOnly") == Some(&Value::Bool(true)) {
+            found.push(path.to_string());
+        }
+        if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+            for (key, property) in properties {
+                let path = match path {
+                    "" => key.to_string(),
+                    _ => format!("{path}.{key}"),
+                };
+                walk(root, property, &path, found);
+            }
+        }
+    }
+
+    let mut found = Vec::new();
+    walk(schema, schema, "", &mut found);
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::FormatRegistry;
+    use serde_json::json;
+
+    #[test]
+    fn it_ignores_format_and_key_order() {
+        let registry = FormatRegistry::default();
+        let json = include_str!("../config.json");
+        let yaml = "database_url: postgresql://user@database:5432/db\n\
+                    s3_path: bucket.teach-rs.tweede.golf\n\
+                    base_url: https://config.teach-rs.tweede.golf\n\
+                    port: \"1234\"\n";
+        let old: Config = registry.by_name("json").unwrap().deserialize(json).unwrap();
+        let new: Config = registry.by_name("yaml").unwrap().deserialize(yaml).unwrap();
+        assert_eq!(config_diff(&old, &new).unwrap(), []);
+    }
+
+    #[test]
+    fn it_lists_changes_per_field() {
+        let changes = diff(
+            &json!({"port": 1, "database": {"url": "a", "pool": 5}, "gone": true}),
+            &json!({"port": 2, "database": {"url": "a", "timeout": "30s"}}),
+        );
+        let shown: Vec<String> = changes.iter().map(Change::to_string).collect();
+        assert_eq!(
+            shown,
+            [
+                "~ port: 1 -> 2",
+                "- database.pool: 5",
+                "+ database.timeout: \"30s\"",
+                "- gone: true",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_redacts_secrets() {
+        let registry = FormatRegistry::default();
+        let json = include_str!("../config.json");
+        let old: Config = registry.by_name("json").unwrap().deserialize(json).unwrap();
+        let changed = json.replace("user@database", "admin@database");
+        let new: Config = registry
+            .by_name("json")
+            .unwrap()
+            .deserialize(&changed)
+            .unwrap();
+
+        let changes = config_diff(&old, &new).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "database.url");
+        let out = serde_json::to_string(&changes).unwrap();
+        assert!(!out.contains("admin@database"), "{out}");
+    }
+}
@@ -0,0 +1,846 @@
+use std::ops::Range;
+
+use serde_json::{Map, Value};
+
+use crate::{ConfigDeserializer, Error, migrate, query};
+
+/// Set the value at `path` (dotted, or a JSON pointer, see `query::get`) in `contents`, leaving
+/// comments, key order and indentation everywhere else as they were written. Keys that aren't
+/// there yet are added, along with any maps on the way to them.
+///
+/// The result is read back to make sure the edit changed that one value and nothing else.
+pub fn set(
+    format: &dyn ConfigDeserializer,
+    contents: &str,
+    path: &str,
+    value: &Value,
+) -> Result<String, Error> {
+    let mut document: Value = format.deserialize(contents)?;
+    if query::get(&document, path).is_err() {
+        // Adding `database.url` next to a `database_url` would make the config unreadable
+        let version = migrate::version(&document)?;
+        let mut migrated = document.clone();
+        migrate::apply(&mut migrated, version);
+        if migrated != document {
+            return Err(Error::Invalid(format!(
+                "`{path}` isn't set, and the config is written for version {version}, `migrate` \
+                 it first"
+            )));
+        }
+    }
+
+    let edited = format.set(contents, path, value)?;
+    set_in(&mut document, path, &query::segments(path), value.clone())?;
+    if format.deserialize::<Value>(&edited).ok() != Some(document) {
+        let msg = format!("couldn't set `{path}` without rewriting the rest of the file");
+        return Err(Error::Unsupported(msg));
+    }
+    Ok(edited)
+}
+
+/// `set` for an already parsed document. Fails if `segments` goes through something that isn't
+/// a map or a list, which `format.set` should have refused already, unless it read the document
+/// differently.
+fn set_in(
+    document: &mut Value,
+    path: &str,
+    segments: &[String],
+    value: Value,
+) -> Result<(), Error> {
+    let Some((last, parents)) = segments.split_last() else {
+        *document = value;
+        return Ok(());
+    };
+    let mut node = document;
+    for (depth, segment) in parents.iter().enumerate() {
+        if node.is_null() {
+            *node = Value::Object(Map::new());
+        }
+        node = match node {
+            Value::Array(items) => {
+                let len = items.len();
+                segment
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|index| items.get_mut(index))
+                    .ok_or_else(|| out_of_range(path, len))?
+            }
+            Value::Object(map) => map
+                .entry(segment.as_str())
+                .or_insert_with(|| Value::Object(Map::new())),
+            _ => return Err(not_a_collection(path, segments, depth)),
+        };
+    }
+    if node.is_null() {
+        *node = Value::Object(Map::new());
+    }
+    match node {
+        Value::Array(items) => {
+            let len = items.len();
+            *last
+                .parse::<usize>()
+                .ok()
+                .and_then(|index| items.get_mut(index))
+                .ok_or_else(|| out_of_range(path, len))? = value;
+        }
+        Value::Object(map) => {
+            map.insert(last.clone(), value);
+        }
+        _ => return Err(not_a_collection(path, segments, parents.len())),
+    }
+    Ok(())
+}
+
+/// `value` nested in maps, one for each of `keys`
+fn nest(keys: &[String], value: &Value) -> Value {
+    keys.iter().rev().fold(value.clone(), |value, key| {
+        Value::Object(Map::from_iter([(key.clone(), value)]))
+    })
+}
+
+fn splice(contents: &str, range: Range<usize>, text: &str) -> String {
+    let mut edited = contents.to_string();
+    edited.replace_range(range, text);
+    edited
+}
+
+/// The whitespace the line `pos` is on starts with
+fn line_indent(contents: &str, pos: usize) -> &str {
+    let start = contents[..pos].rfind('\n').map_or(0, |i| i + 1);
+    let line = &contents[start..];
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+fn not_a_collection(path: &str, segments: &[String], depth: usize) -> Error {
+    let parent = segments[..depth].join(".");
+    Error::Usage(format!(
+        "can't set `{path}`, `{parent}` isn't a map or a list"
+    ))
+}
+
+/// `serde_json` keeps the last of several values for the same key, where the editors would find
+/// the first one
+fn duplicate_key(key: &str) -> Error {
+    Error::Invalid(format!("`{key}` is set more than once in the same map"))
+}
+
+fn out_of_range(path: &str, len: usize) -> Error {
+    Error::Usage(format!(
+        "can't set `{path}`, the list only has {len} item(s)"
+    ))
+}
+
+/// `ConfigDeserializer::set` for JSON. A new key goes after the last member of its map, laid
+/// out like the members already there.
+pub(crate) fn json(contents: &str, path: &str, value: &Value) -> Result<String, Error> {
+    let segments = query::segments(path);
+    let root = JsonParser {
+        text: contents,
+        pos: 0,
+    }
+    .value()?;
+
+    let mut node = &root;
+    for (depth, segment) in segments.iter().enumerate() {
+        node = match &node.kind {
+            JsonKind::Object(members) => match members.iter().find(|m| m.key == *segment) {
+                Some(member) => &member.value,
+                None => {
+                    let value = nest(&segments[depth + 1..], value);
+                    return Ok(json_insert(contents, node, members, segment, &value));
+                }
+            },
+            JsonKind::Array(items) => {
+                let index = segment.parse::<usize>().unwrap_or(usize::MAX);
+                items
+                    .get(index)
+                    .ok_or_else(|| out_of_range(path, items.len()))?
+            }
+            JsonKind::Scalar => return Err(not_a_collection(path, &segments, depth)),
+        };
+    }
+    let indent = line_indent(contents, node.span.start);
+    Ok(splice(
+        contents,
+        node.span.clone(),
+        &json_value(value, indent),
+    ))
+}
+
+/// `value` as JSON, with any lines after the first indented by `indent`
+fn json_value(value: &Value, indent: &str) -> String {
+    let json = serde_json::to_string_pretty(value).expect("values are always valid JSON");
+    json.replace('\n', &format!("\n{indent}"))
+}
+
+fn json_insert(
+    contents: &str,
+    object: &JsonNode,
+    members: &[Member],
+    key: &str,
+    value: &Value,
+) -> String {
+    let key = serde_json::to_string(key).expect("strings are always valid JSON");
+    let Some(last) = members.last() else {
+        let indent = line_indent(contents, object.span.start);
+        let text = format!("{key}: {}", json_value(value, indent));
+        return splice(contents, object.span.start + 1..object.span.end - 1, &text);
+    };
+
+    // Whatever separates the last two members (eg. `,\n  `), and the key from its value
+    let separator = match members {
+        [.., before, last] => contents[before.value.span.end..last.key_span.start].to_string(),
+        _ => match &contents[object.span.start + 1..last.key_span.start] {
+            "" => ", ".to_string(),
+            space => format!(",{space}"),
+        },
+    };
+    let colon = &contents[last.key_span.end..last.value.span.start];
+    let indent = line_indent(contents, last.key_span.start);
+    let text = format!("{separator}{key}{colon}{}", json_value(value, indent));
+    let end = last.value.span.end;
+    splice(contents, end..end, &text)
+}
+
+/// A JSON value and where it is in the text
+struct JsonNode {
+    span: Range<usize>,
+    kind: JsonKind,
+}
+
+enum JsonKind {
+    Object(Vec<Member>),
+    Array(Vec<JsonNode>),
+    Scalar,
+}
+
+struct Member {
+    key: String,
+    /// Where the key is, quotes included
+    key_span: Range<usize>,
+    value: JsonNode,
+}
+
+/// Just enough of a JSON parser to know where every value is. The contents have been through
+/// `serde_json` already, so it can assume they're valid.
+struct JsonParser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn error(&self) -> Error {
+        Error::Invalid(format!("unexpected JSON at byte {}", self.pos))
+    }
+
+    fn eat(&mut self, byte: u8) -> Result<(), Error> {
+        self.skip_whitespace();
+        match self.peek() == Some(byte) {
+            true => {
+                self.pos += 1;
+                Ok(())
+            }
+            false => Err(self.error()),
+        }
+    }
+
+    /// Whether the list or map being parsed goes on, after skipping the `,` if it does
+    fn more(&mut self, close: u8) -> Result<bool, Error> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b',') => {
+                self.pos += 1;
+                Ok(true)
+            }
+            Some(byte) if byte == close => {
+                self.pos += 1;
+                Ok(false)
+            }
+            _ => Err(self.error()),
+        }
+    }
+
+    fn value(&mut self) -> Result<JsonNode, Error> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let kind = match self.peek() {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                } else {
+                    loop {
+                        self.skip_whitespace();
+                        let key_start = self.pos;
+                        self.string()?;
+                        let key_span = key_start..self.pos;
+                        let key: String = serde_json::from_str(&self.text[key_span.clone()])
+                            .map_err(Error::Json)?;
+                        if members.iter().any(|member: &Member| member.key == key) {
+                            return Err(duplicate_key(&key));
+                        }
+                        self.eat(b':')?;
+                        members.push(Member {
+                            key,
+                            key_span,
+                            value: self.value()?,
+                        });
+                        if !self.more(b'}')? {
+                            break;
+                        }
+                    }
+                }
+                JsonKind::Object(members)
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                } else {
+                    loop {
+                        items.push(self.value()?);
+                        if !self.more(b']')? {
+                            break;
+                        }
+                    }
+                }
+                JsonKind::Array(items)
+            }
+            Some(b'"') => {
+                self.string()?;
+                JsonKind::Scalar
+            }
+            Some(_) => {
+                while !matches!(
+                    self.peek(),
+                    None | Some(b',' | b'}' | b']' | b' ' | b'\t' | b'\n' | b'\r')
+                ) {
+                    self.pos += 1;
+                }
+                JsonKind::Scalar
+            }
+            None => return Err(self.error()),
+        };
+        Ok(JsonNode {
+            span: start..self.pos,
+            kind,
+        })
+    }
+
+    fn string(&mut self) -> Result<(), Error> {
+        if self.peek() != Some(b'"') {
+            return Err(self.error());
+        }
+        self.pos += 1;
+        loop {
+            match self.peek() {
+                Some(b'\\') => self.pos += 2,
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                Some(_) => self.pos += 1,
+                None => return Err(self.error()),
+            }
+        }
+    }
+}
+
+/// `ConfigDeserializer::set` for YAML. Only block style can be found its way around in: maps
+/// and lists written in flow style (`{a: 1}`, `[a, b]`) can be replaced as a whole, but not
+/// edited inside, and neither can maps inside lists. A new key goes after the last key of its
+/// map, at the same indentation.
+pub(crate) fn yaml(contents: &str, path: &str, value: &Value) -> Result<String, Error> {
+    let segments = query::segments(path);
+    if segments.is_empty() {
+        return Err(Error::Usage("Please specify the key to set".to_string()));
+    }
+    let doc = YamlDocument::new(contents);
+
+    let mut node = YamlNode::Block {
+        key: None,
+        lines: 0..doc.lines.len(),
+    };
+    for (depth, segment) in segments.iter().enumerate() {
+        let (key, lines) = match &node {
+            YamlNode::Block { key, lines } => (key, lines),
+            YamlNode::Inline(span) if contents[span.clone()].starts_with(['{', '[']) => {
+                let msg = format!("can't set `{path}` inside a flow style YAML collection");
+                return Err(Error::Unsupported(msg));
+            }
+            YamlNode::Inline(_) => return Err(not_a_collection(path, &segments, depth)),
+        };
+        let found = match doc.is_list(lines.clone()) {
+            true => doc.item(lines.clone(), segment, path)?,
+            false => doc.entry(lines.clone(), segment)?,
+        };
+        node = match found {
+            Some(found) => found,
+            None if doc.is_list(lines.clone()) => {
+                return Err(not_a_collection(path, &segments, depth));
+            }
+            None => {
+                let value = nest(&segments[depth + 1..], value);
+                return Ok(doc.insert(key.as_ref(), lines.clone(), segment, &value));
+            }
+        };
+    }
+
+    match node {
+        YamlNode::Inline(span) => Ok(splice(contents, span, &yaml_inline(value))),
+        YamlNode::Block { key: None, .. } => unreachable!("there is at least one segment"),
+        YamlNode::Block {
+            key: Some(key),
+            lines,
+        } => {
+            // Everything after `key:` goes, block and all
+            let end = match lines.is_empty() {
+                true => key.colon_end,
+                false => doc.lines[lines.end - 1].end,
+            };
+            let text = match is_collection(value) {
+                true => format!("\n{}", yaml_block(value, key.indent + doc.indent_width())),
+                false => format!(" {}", yaml_inline(value)),
+            };
+            Ok(splice(contents, key.colon_end..end, &text))
+        }
+    }
+}
+
+/// A value in a YAML document
+enum YamlNode {
+    /// The whole document, or the lines below `key:`
+    Block {
+        key: Option<YamlKey>,
+        /// Indices into `YamlDocument::lines`, empty when nothing follows `key:`
+        lines: Range<usize>,
+    },
+    /// A value on the same line as its key, or its `-`
+    Inline(Range<usize>),
+}
+
+struct YamlKey {
+    indent: usize,
+    /// Right after the `:`
+    colon_end: usize,
+    /// The end of the line the key is on
+    line_end: usize,
+}
+
+/// A line that isn't blank or just a comment
+struct YamlLine {
+    /// Where the line starts in the text, after the indentation
+    start: usize,
+    /// Where it ends, before the line break
+    end: usize,
+    indent: usize,
+}
+
+struct YamlDocument<'a> {
+    text: &'a str,
+    lines: Vec<YamlLine>,
+}
+
+impl<'a> YamlDocument<'a> {
+    fn new(text: &'a str) -> Self {
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        for line in text.split_inclusive('\n') {
+            let start = offset;
+            offset += line.len();
+            let line = line.trim_end_matches(['\n', '\r']);
+            let content = line.trim_start_matches(' ');
+            if content.trim().is_empty()
+                || content.starts_with('#')
+                || content.starts_with("---")
+                || content.starts_with('%')
+            {
+                continue;
+            }
+            let indent = line.len() - content.len();
+            lines.push(YamlLine {
+                start: start + indent,
+                end: start + line.len(),
+                indent,
+            });
+        }
+        Self { text, lines }
+    }
+
+    fn content(&self, line: &YamlLine) -> &'a str {
+        &self.text[line.start..line.end]
+    }
+
+    /// The indentation nested blocks get, going by the first one in the document
+    fn indent_width(&self) -> usize {
+        self.lines
+            .iter()
+            .map(|line| line.indent)
+            .filter(|indent| *indent > 0)
+            .min()
+            .unwrap_or(2)
+    }
+
+    /// The lines of `lines` at the indentation of the first one
+    fn children(&self, lines: Range<usize>) -> impl Iterator<Item = usize> + '_ {
+        let indent = self.lines.get(lines.start).map(|line| line.indent);
+        lines.filter(move |i| Some(self.lines[*i].indent) == indent)
+    }
+
+    fn is_list(&self, lines: Range<usize>) -> bool {
+        self.lines
+            .get(lines.start)
+            .filter(|_| !lines.is_empty())
+            .is_some_and(|line| is_item(self.content(line)))
+    }
+
+    /// The value of `key` in the map on `lines`
+    fn entry(&self, lines: Range<usize>, key: &str) -> Result<Option<YamlNode>, Error> {
+        let mut found = self.children(lines.clone()).filter_map(|i| {
+            yaml_key(self.content(&self.lines[i]))
+                .filter(|(name, _)| name == key)
+                .map(|(_, colon)| (i, colon))
+        });
+        let Some((i, colon)) = found.next() else {
+            return Ok(None);
+        };
+        if found.next().is_some() {
+            return Err(duplicate_key(key));
+        }
+        let line = &self.lines[i];
+        let colon_end = line.start + colon;
+        let span = inline_value(self.text, colon_end..line.end)?;
+        if !span.is_empty() {
+            return Ok(Some(YamlNode::Inline(span)));
+        }
+        // The value is below the key: more indented, or a list at the same indentation
+        let end = (i + 1..lines.end)
+            .find(|j| {
+                let below = &self.lines[*j];
+                below.indent < line.indent
+                    || (below.indent == line.indent && !is_item(self.content(below)))
+            })
+            .unwrap_or(lines.end);
+        Ok(Some(YamlNode::Block {
+            key: Some(YamlKey {
+                indent: line.indent,
+                colon_end,
+                line_end: line.end,
+            }),
+            lines: i + 1..end,
+        }))
+    }
+
+    /// Item `index` of the list on `lines`
+    fn item(
+        &self,
+        lines: Range<usize>,
+        index: &str,
+        path: &str,
+    ) -> Result<Option<YamlNode>, Error> {
+        let items: Vec<usize> = self.children(lines).collect();
+        let Some(i) = index.parse::<usize>().ok().and_then(|i| items.get(i)) else {
+            return Err(out_of_range(path, items.len()));
+        };
+        let line = &self.lines[*i];
+        let span = inline_value(self.text, line.start + 1..line.end)?;
+        if span.is_empty() || yaml_key(&self.text[span.clone()]).is_some() {
+            let msg = format!("can't set `{path}`, only plain values in YAML lists can be edited");
+            return Err(Error::Unsupported(msg));
+        }
+        Ok(Some(YamlNode::Inline(span)))
+    }
+
+    /// Add `key: value` to the map on `lines`, which belongs to `parent` (or is the whole
+    /// document)
+    fn insert(
+        &self,
+        parent: Option<&YamlKey>,
+        lines: Range<usize>,
+        key: &str,
+        value: &Value,
+    ) -> String {
+        let (indent, at) = match (self.lines.get(lines.start), parent) {
+            (Some(first), _) if !lines.is_empty() => (first.indent, self.lines[lines.end - 1].end),
+            (_, Some(parent)) => (parent.indent + self.indent_width(), parent.line_end),
+            // An empty document
+            _ => {
+                let text = match self.text.is_empty() || self.text.ends_with('\n') {
+                    true => format!("{}\n", yaml_entry(key, value, 0, 2)),
+                    false => format!("\n{}\n", yaml_entry(key, value, 0, 2)),
+                };
+                return splice(self.text, self.text.len()..self.text.len(), &text);
+            }
+        };
+        let entry = yaml_entry(key, value, indent, self.indent_width());
+        splice(self.text, at..at, &format!("\n{entry}"))
+    }
+}
+
+fn is_item(content: &str) -> bool {
+    content == "-" || content.starts_with("- ")
+}
+
+/// The key `content` (a line without its indentation) starts with, and where its `:` ends
+fn yaml_key(content: &str) -> Option<(String, usize)> {
+    let (key, rest) = match content.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let end = closing_quote(content, quote)?;
+            let key = match quote {
+                '"' => serde_json::from_str(&content[..end]).ok()?,
+                _ => content[1..end - 1].replace("''", "'"),
+            };
+            (key, &content[end..])
+        }
+        '-' | '?' | '{' | '[' => return None,
+        _ => {
+            let colon = content
+                .match_indices(':')
+                .map(|(i, _)| i)
+                .find(|i| matches!(content.as_bytes().get(i + 1), None | Some(b' ')))?;
+            (content[..colon].trim_end().to_string(), &content[colon..])
+        }
+    };
+    let rest = rest.trim_start_matches(' ');
+    rest.starts_with(':')
+        .then(|| (key, content.len() - rest.len() + 1))
+}
+
+/// Where the closing quote of the string `s` starts with is, plus one
+fn closing_quote(s: &str, quote: char) -> Option<usize> {
+    let mut chars = s.char_indices().skip(1).peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if quote == '"' => {
+                chars.next();
+            }
+            // `''` is an escaped quote in single quoted strings
+            '\'' if quote == '\'' && chars.peek().is_some_and(|(_, c)| *c == '\'') => {
+                chars.next();
+            }
+            c if c == quote => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Where the value on the rest of a line (`range`, after `key:` or `-`) is, without the
+/// whitespace and comment around it. Empty when there's no value there.
+fn inline_value(text: &str, range: Range<usize>) -> Result<Range<usize>, Error> {
+    let rest = &text[range.clone()];
+    let start = range.start + rest.len() - rest.trim_start().len();
+    let value = &text[start..range.end];
+    let len = match value.chars().next() {
+        None | Some('#') => 0,
+        Some(quote @ ('"' | '\'')) => closing_quote(value, quote).ok_or_else(|| {
+            Error::Unsupported("quoted YAML strings over several lines can't be edited".to_string())
+        })?,
+        Some('|' | '>') => {
+            let msg = "YAML block scalars (`|`, `>`) can't be edited";
+            return Err(Error::Unsupported(msg.to_string()));
+        }
+        Some(_) => value.find(" #").unwrap_or(value.len()),
+    };
+    Ok(start..start + value[..len].trim_end().len())
+}
+
+fn is_collection(value: &Value) -> bool {
+    match value {
+        Value::Object(map) => !map.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        _ => false,
+    }
+}
+
+/// `value` on a single line: scalars as YAML, collections in flow style
+fn yaml_inline(value: &Value) -> String {
+    match is_collection(value) {
+        true => serde_json::to_string(value).expect("values are always valid JSON"),
+        false => serde_yaml::to_string(value)
+            .expect("values are always valid YAML")
+            .trim_end()
+            .to_string(),
+    }
+}
+
+/// `value` in block style, every line indented by `indent` spaces
+fn yaml_block(value: &Value, indent: usize) -> String {
+    let yaml = serde_yaml::to_string(value).expect("values are always valid YAML");
+    let pad = " ".repeat(indent);
+    yaml.lines()
+        .map(|line| format!("{pad}{line}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn yaml_entry(key: &str, value: &Value, indent: usize, width: usize) -> String {
+    let key = yaml_inline(&Value::String(key.to_string()));
+    let pad = " ".repeat(indent);
+    match is_collection(value) {
+        true => format!("{pad}{key}:\n{}", yaml_block(value, indent + width)),
+        false => format!("{pad}{key}: {}", yaml_inline(value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const YAML: &str = "\
+# Where the app listens
+port: 1234    # not 80, that needs root
+base_urls:
+  - https://a.example
+  - 'https://b.example'
+database:
+    url: \"postgresql://db/app\"
+    pool_size: 4
+";
+
+    const JSON: &str = r#"{
+    "port": 1234,
+    "base_urls": ["https://a.example"],
+    "database": {
+        "url": "postgresql://db/app"
+    }
+}
+"#;
+
+    #[test]
+    fn it_edits_yaml_in_place() {
+        let edited = yaml(YAML, "port", &json!(8080)).unwrap();
+        assert_eq!(edited, YAML.replace("1234", "8080"));
+
+        let edited = yaml(YAML, "/database/pool_size", &json!(16)).unwrap();
+        assert_eq!(edited, YAML.replace("pool_size: 4", "pool_size: 16"));
+
+        let edited = yaml(YAML, "base_urls.1", &json!("https://c.example")).unwrap();
+        assert_eq!(
+            edited,
+            YAML.replace("'https://b.example'", "https://c.example")
+        );
+    }
+
+    #[test]
+    fn it_adds_yaml_keys_where_they_belong() {
+        let edited = yaml(YAML, "database.timeout", &json!("1m")).unwrap();
+        assert_eq!(
+            edited,
+            YAML.replace("pool_size: 4\n", "pool_size: 4\n    timeout: 1m\n")
+        );
+
+        let edited = yaml(YAML, "limits.max_body_size", &json!("1MiB")).unwrap();
+        assert!(
+            edited.ends_with("pool_size: 4\nlimits:\n  max_body_size: 1MiB\n"),
+            "{edited}"
+        );
+
+        let edited = yaml(YAML, "base_urls", &json!("https://c.example")).unwrap();
+        assert!(
+            edited.contains("base_urls: https://c.example\ndatabase:"),
+            "{edited}"
+        );
+    }
+
+    #[test]
+    fn it_edits_json_in_place() {
+        let edited = json(JSON, "port", &json!(8080)).unwrap();
+        assert_eq!(edited, JSON.replace("1234", "8080"));
+
+        let edited = json(JSON, "database.pool_size", &json!(16)).unwrap();
+        assert_eq!(
+            edited,
+            JSON.replace(
+                "\"postgresql://db/app\"\n",
+                "\"postgresql://db/app\",\n        \"pool_size\": 16\n"
+            )
+        );
+
+        let edited = json(JSON, "s3", &json!({"bucket": "b"})).unwrap();
+        assert!(
+            edited.ends_with("    },\n    \"s3\": {\n      \"bucket\": \"b\"\n    }\n}\n"),
+            "{edited}"
+        );
+
+        let edited = json(r#"{"a": 1}"#, "b", &json!([1, 2])).unwrap();
+        assert_eq!(edited, "{\"a\": 1, \"b\": [\n  1,\n  2\n]}");
+    }
+
+    #[test]
+    fn it_refuses_paths_through_values() {
+        let err = json(JSON, "port.number", &json!(1)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "can't set `port.number`, `port` isn't a map or a list"
+        );
+        let err = yaml(YAML, "base_urls.5", &json!("x")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "can't set `base_urls.5`, the list only has 2 item(s)"
+        );
+    }
+
+    #[test]
+    fn it_refuses_duplicate_keys() {
+        let err = json(r#"{"port": {"x": 1}, "port": 1}"#, "port.x", &json!(2)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid config: `port` is set more than once in the same map"
+        );
+        let err = yaml("port: 1\nport: 2\n", "port", &json!(3)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid config: `port` is set more than once in the same map"
+        );
+    }
+
+    #[test]
+    fn it_refuses_paths_the_document_does_not_have() {
+        let mut document = json!({"port": 1234, "base_urls": []});
+        let err = set_in(
+            &mut document,
+            "port.x",
+            &query::segments("port.x"),
+            json!(2),
+        );
+        assert!(matches!(err, Err(Error::Usage(_))), "{err:?}");
+        let err = set_in(
+            &mut document,
+            "base_urls.0",
+            &query::segments("base_urls.0"),
+            json!(2),
+        );
+        assert!(matches!(err, Err(Error::Usage(_))), "{err:?}");
+
+        set_in(&mut document, "a.b", &query::segments("a.b"), json!(2)).unwrap();
+        assert_eq!(document["a"], json!({"b": 2}));
+    }
+
+    #[test]
+    fn it_checks_the_edit() {
+        let format = crate::YmlDeserializer::new();
+        let edited = set(&format, YAML, "database.url", &json!("postgresql://x/y")).unwrap();
+        assert!(edited.starts_with("# Where the app listens\n"));
+
+        let flat = "port: 1234\ndatabase_url: postgresql://db/app\n";
+        let err = set(&format, flat, "database.pool_size", &json!(2)).unwrap_err();
+        assert!(err.to_string().contains("`migrate` it first"), "{err}");
+    }
+}
@@ -0,0 +1,576 @@
+use std::fmt;
+
+use serde::{
+    Deserialize,
+    de::{self, Visitor},
+    forward_to_deserialize_any,
+};
+
+use crate::legacy;
+
+#[derive(Debug)]
+#[non_exhaustive]
+/// Everything that can go wrong reading a config
+pub enum Error {
+    /// Something went wrong deserializing JSON
+    Json(serde_json::Error),
+    /// Something went wrong deserializing YAML
+    Yaml(serde_yaml::Error),
+    /// Something went wrong deserializing TOML
+    Toml(toml::de::Error),
+    /// Something went wrong parsing INI
+    Ini(ini::ParseError),
+    /// Something went wrong deserializing an already parsed tree (INI values, merged layers)
+    Value(serde_json::Error),
+    /// Something went wrong serializing a config
+    Serialize(String),
+    /// The format can't do what was asked of it
+    Unsupported(String),
+    /// The config parsed fine but doesn't make sense
+    Invalid(String),
+    /// The config couldn't be read
+    Io(std::io::Error),
+    /// We couldn't tell which format the config is in
+    UnknownFormat,
+    /// The command line didn't make sense
+    Usage(String),
+    /// The selected profile isn't defined by the config
+    UnknownProfile {
+        name: String,
+        /// Every profile the config does define
+        available: Vec<String>,
+    },
+    /// A value that was asked for isn't in the config
+    MissingKey {
+        key: String,
+        /// A key that is in the config, and looks like it was meant
+        suggestion: Option<String>,
+    },
+}
+
+impl Error {
+    /// The process exit code for this class of error
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::Usage(_) | Self::UnknownProfile { .. } => 2,
+            Self::Io(_) => 3,
+            Self::UnknownFormat | Self::Unsupported(_) => 4,
+            Self::Json(_)
+            | Self::Yaml(_)
+            | Self::Toml(_)
+            | Self::Ini(_)
+            | Self::Value(_)
+            | Self::Serialize(_) => 5,
+            Self::Invalid(_) => 6,
+            Self::MissingKey { .. } => 7,
+        }
+    }
+
+    /// The error message, without any location information baked into it
+    fn message(&self) -> String {
+        match self {
+            Self::Json(e) | Self::Value(e) => recover(&e.to_string()).message,
+            Self::Yaml(e) => recover(&e.to_string()).message,
+            Self::Toml(e) => recover(e.message()).message,
+            Self::Ini(e) => e.msg.to_string(),
+            Self::Io(e) => e.to_string(),
+            Self::Invalid(msg) | Self::Serialize(msg) | Self::Unsupported(msg) => msg.clone(),
+            Self::UnknownFormat => "unable to detect config format, use `--format`".to_string(),
+            Self::Usage(msg) => msg.clone(),
+            Self::UnknownProfile { name, available } => format!(
+                "unknown profile `{name}`, available profiles: {}",
+                available.join(", ")
+            ),
+            Self::MissingKey { key, suggestion } => match suggestion {
+                Some(suggestion) => format!("`{key}` isn't set, did you mean `{suggestion}`?"),
+                None => format!("`{key}` isn't set"),
+            },
+        }
+    }
+
+    /// 1-based line and column of the error, and the key it happened at, if we can tell
+    fn position(&self, contents: &str) -> (Option<Location>, Option<String>) {
+        match self {
+            Self::Json(e) | Self::Value(e) if e.line() > 0 => {
+                let location = Location {
+                    line: e.line(),
+                    column: e.column().max(1),
+                };
+                (Some(location), None)
+            }
+            Self::Json(e) | Self::Value(e) => {
+                let recovered = recover(&e.to_string());
+                (recovered.location, recovered.key)
+            }
+            Self::Yaml(e) => {
+                let recovered = recover(&e.to_string());
+                let location = e.location().map(|l| Location {
+                    line: l.line(),
+                    column: l.column(),
+                });
+                (location.or(recovered.location), recovered.key)
+            }
+            Self::Toml(e) => {
+                let recovered = recover(e.message());
+                let location = e
+                    .span()
+                    .map(|span| Location::from_offset(contents, span.start));
+                match (location, recovered.key) {
+                    (Some(location), key) => (Some(location), key),
+                    // Once erased, toml only reports the table the error happened in, the key
+                    // gets us a lot closer
+                    (None, Some(key)) => (None, Some(key)),
+                    (None, None) => (recovered.location, None),
+                }
+            }
+            Self::Ini(e) => {
+                let location = Location {
+                    line: e.line + 1,
+                    column: e.col + 1,
+                };
+                (Some(location), None)
+            }
+            _ => (None, None),
+        }
+    }
+}
+
+/// What we could dig back out of an error message
+struct Recovered {
+    message: String,
+    location: Option<Location>,
+    key: Option<String>,
+}
+
+/// Errors that passed through `erased_serde` only survive as text, and each format bakes the
+/// position into that text differently:
+///
+/// - serde_json and serde_yaml append ` at line X column Y`
+/// - serde_yaml prefixes the path of the offending key, `database.url: ...`
+/// - toml renders a whole report, `TOML parse error at line X, column Y`, a snippet, the message
+///   and `in `key``
+fn recover(text: &str) -> Recovered {
+    if let Some(rest) = text.strip_prefix("TOML parse error at ") {
+        let mut lines = rest.lines();
+        let location = lines.next().and_then(parse_line_column);
+        let mut message = Vec::new();
+        let mut key = None;
+        for line in lines {
+            let trimmed = line.trim_start();
+            let is_snippet = trimmed.starts_with('|')
+                || trimmed
+                    .split_once(" |")
+                    .is_some_and(|(n, _)| n.chars().all(|c| c.is_ascii_digit()));
+            if let Some(k) = line.strip_prefix("in `").and_then(|k| k.strip_suffix('`')) {
+                key = Some(k.to_string());
+            } else if !trimmed.is_empty() && !is_snippet {
+                message.push(trimmed);
+            }
+        }
+        return Recovered {
+            message: message.join(" "),
+            location,
+            key,
+        };
+    }
+
+    let (mut message, location) = match text.rfind(" at line ") {
+        Some(i) => (&text[..i], parse_line_column(&text[i + 4..])),
+        None => (text, None),
+    };
+
+    let mut key = None;
+    if let Some((path, rest)) = message.split_once(": ") {
+        let is_path = !path.is_empty()
+            && path
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '[' | ']'));
+        if is_path {
+            key = Some(path.to_string());
+            message = rest;
+        }
+    }
+
+    Recovered {
+        message: message.to_string(),
+        location,
+        key,
+    }
+}
+
+/// Parse `line X column Y` / `line X, column Y`
+fn parse_line_column(text: &str) -> Option<Location> {
+    let rest = text.strip_prefix("line ")?;
+    let (line, rest) = rest.split_once(|c: char| !c.is_ascii_digit())?;
+    let rest = rest
+        .trim_start_matches([',', ' '])
+        .strip_prefix("column ")?;
+    let column = rest
+        .split(|c: char| !c.is_ascii_digit())
+        .next()?
+        .parse::<usize>()
+        .ok()?;
+    Some(Location {
+        line: line.parse().ok()?,
+        column: column.max(1),
+    })
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            Self::Json(_) => "invalid JSON",
+            Self::Yaml(_) => "invalid YAML",
+            Self::Toml(_) => "invalid TOML",
+            Self::Ini(_) => "invalid INI",
+            Self::Value(_) | Self::Invalid(_) => "invalid config",
+            Self::Serialize(_) => "unable to write config",
+            Self::Io(_) => "unable to read config",
+            Self::UnknownFormat
+            | Self::Unsupported(_)
+            | Self::Usage(_)
+            | Self::UnknownProfile { .. }
+            | Self::MissingKey { .. } => {
+                return f.write_str(&self.message());
+            }
+        };
+        write!(f, "{kind}: {}", self.message())
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Json(e) | Self::Value(e) => Some(e),
+            Self::Yaml(e) => Some(e),
+            Self::Toml(e) => Some(e),
+            Self::Ini(e) => Some(e),
+            Self::Io(e) => Some(e),
+            Self::Invalid(_)
+            | Self::Serialize(_)
+            | Self::Unsupported(_)
+            | Self::UnknownFormat
+            | Self::Usage(_)
+            | Self::UnknownProfile { .. }
+            | Self::MissingKey { .. } => None,
+        }
+    }
+}
+
+/// 1-based position within a config
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    fn from_offset(contents: &str, offset: usize) -> Self {
+        let before = &contents[..offset.min(contents.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+        Self { line, column }
+    }
+}
+
+/// An `Error` along with everything we could work out about where it happened, ready to be shown
+/// to a human
+#[derive(Debug)]
+pub struct ConfigError(Box<Report>);
+
+#[derive(Debug)]
+struct Report {
+    error: Error,
+    path: Option<String>,
+    location: Option<Location>,
+    key: Option<String>,
+    snippet: Option<String>,
+    suggestion: Option<String>,
+}
+
+impl ConfigError {
+    /// Attach the path of the config the error came from
+    pub fn with_path(mut self, path: &str) -> Self {
+        self.0.path = Some(path.to_string());
+        self
+    }
+
+    /// Attach the config the error came from, so we can point at the offending line and look for
+    /// misspelled keys. `fields` are the keys the target type knows about.
+    pub fn with_source(mut self, path: &str, contents: &str, fields: &[&str]) -> Self {
+        self.0.path = Some(path.to_string());
+        (self.0.location, self.0.key) = self.0.error.position(contents);
+
+        let message = self.0.error.message();
+        if let Some(missing) = quoted_after(&message, "missing field `") {
+            // A missing field is often just a misspelled one, or a misspelled old name of one, so
+            // go looking for it
+            let meant = |key: &str| {
+                fields.iter().copied().chain([missing.as_str()]).find(|f| {
+                    is_similar(key, f) && (*f == missing || legacy::renamed(f) == Some(&missing))
+                })
+            };
+            let found = document_keys(contents)
+                .into_iter()
+                .filter(|(key, _)| !fields.contains(&key.as_str()))
+                .find_map(|(key, line)| Some((meant(&key)?, key, line)));
+            if let Some((meant, found, line)) = found {
+                self.0.suggestion = Some(format!("found `{found}`, did you mean `{meant}`?"));
+                self.0.location = Some(Location { line, column: 1 });
+            }
+            self.0.key = Some(missing);
+        } else if let Some(unknown) = quoted_after(&message, "unknown field `")
+            .or_else(|| quoted_after(&message, "duplicate field `"))
+        {
+            if let Some(known) = fields.iter().find(|f| is_similar(f, &unknown)) {
+                self.0.suggestion = Some(format!("did you mean `{known}`?"));
+            }
+            if self.0.location.is_none() {
+                self.0.location = document_keys(contents)
+                    .into_iter()
+                    .find(|(key, _)| *key == unknown)
+                    .map(|(_, line)| Location { line, column: 1 });
+            }
+            self.0.key = Some(unknown);
+        }
+
+        // All we know is which key it was, so point at where that key is set
+        if let (None, Some(key)) = (self.0.location, &self.0.key) {
+            let last = key.rsplit('.').next().unwrap_or(key);
+            self.0.location = document_keys(contents)
+                .into_iter()
+                .find(|(k, _)| k == last)
+                .map(|(_, line)| Location { line, column: 1 });
+        }
+
+        if let Some(location) = self.0.location {
+            let line = contents.lines().nth(location.line - 1).unwrap_or_default();
+            if self.0.key.is_none() {
+                self.0.key = line_key(line).map(str::to_string);
+            }
+            self.0.snippet = Some(line.to_string());
+        }
+        self
+    }
+
+    /// The process exit code for this class of error
+    pub fn exit_code(&self) -> u8 {
+        self.0.error.exit_code()
+    }
+
+    /// What went wrong
+    pub fn error(&self) -> &Error {
+        &self.0.error
+    }
+
+    /// The config the error came from, if known
+    pub fn path(&self) -> Option<&str> {
+        self.0.path.as_deref()
+    }
+
+    /// Where in the config the error happened, if known
+    pub fn location(&self) -> Option<Location> {
+        self.0.location
+    }
+
+    /// The key the error is about, if known
+    pub fn key(&self) -> Option<&str> {
+        self.0.key.as_deref()
+    }
+}
+
+impl From<Error> for ConfigError {
+    fn from(error: Error) -> Self {
+        Self(Box::new(Report {
+            error,
+            path: None,
+            location: None,
+            key: None,
+            snippet: None,
+            suggestion: None,
+        }))
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "error: {}", self.0.error)?;
+        if let Some(key) = &self.0.key {
+            write!(f, " (key `{key}`)")?;
+        }
+
+        match (&self.0.path, self.0.location) {
+            (Some(path), Some(Location { line, column })) => {
+                write!(f, "\n  --> {path}:{line}:{column}")?
+            }
+            (Some(path), None) => write!(f, "\n  --> {path}")?,
+            _ => {}
+        }
+
+        if let (Some(snippet), Some(Location { line, column })) = (&self.0.snippet, self.0.location)
+        {
+            let gutter = " ".repeat(line.to_string().len());
+            write!(f, "\n{gutter} |")?;
+            write!(f, "\n{line} | {snippet}")?;
+            // Keep tabs so the caret lines up with the snippet above it
+            let padding: String = snippet
+                .chars()
+                .take(column - 1)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            write!(f, "\n{gutter} | {padding}^")?;
+        }
+
+        if let Some(suggestion) = &self.0.suggestion {
+            write!(f, "\n  = help: {suggestion}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0.error)
+    }
+}
+
+/// The field names of a struct, as seen by its `Deserialize` impl
+pub fn struct_fields<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    struct Introspect<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> de::Deserializer<'de> for Introspect<'_> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _: &'static str,
+            fields: &'static [&'static str],
+            _: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(de::Error::custom("done"))
+        }
+
+        forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(Introspect(&mut fields));
+    fields
+}
+
+/// Keys that look like they're being assigned to in a config, with their 1-based line numbers
+fn document_keys(contents: &str) -> Vec<(String, usize)> {
+    contents
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| line_key(line).map(|key| (key.to_string(), i + 1)))
+        .collect()
+}
+
+/// The key on a `key: value` / `"key": value` / `key = value` line
+fn line_key(line: &str) -> Option<&str> {
+    let end = line.find([':', '='])?;
+    let key = line[..end].trim().trim_matches(['"', '\'']);
+    let valid = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    valid.then_some(key)
+}
+
+fn quoted_after(message: &str, prefix: &str) -> Option<String> {
+    let rest = &message[message.find(prefix)? + prefix.len()..];
+    Some(rest[..rest.find('`')?].to_string())
+}
+
+pub(crate) fn is_similar(a: &str, b: &str) -> bool {
+    a != b && strsim::damerau_levenshtein(a, b) <= (b.len() / 4).max(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, ConfigDeserializer, JsonDeserializer, TomlDeserializer, YmlDeserializer};
+
+    fn located(deserializer: &dyn ConfigDeserializer, path: &str, contents: &str) -> ConfigError {
+        let error = deserializer.deserialize::<Config>(contents).unwrap_err();
+        ConfigError::from(error).with_source(path, contents, struct_fields::<Config>())
+    }
+
+    #[test]
+    fn it_introspects_fields() {
+        assert_eq!(
+            struct_fields::<Config>(),
+            [
+                "port",
+                "base_url",
+                "base_urls",
+                "database",
+                "database_url",
+                "s3",
+                "s3_path",
+                "features",
+                "limits"
+            ]
+        );
+    }
+
+    #[test]
+    fn it_suggests_misspelled_keys() {
+        let contents = include_str!("../config.yml").replace("database_url", "databse_url");
+        let error = located(&YmlDeserializer::new(), "config.yml", &contents);
+
+        assert_eq!(error.key(), Some("database"));
+        assert_eq!(error.location(), Some(Location { line: 4, column: 1 }));
+        assert_eq!(
+            error.0.suggestion.as_deref(),
+            Some("found `databse_url`, did you mean `database_url`?")
+        );
+        assert_eq!(error.exit_code(), 5);
+
+        let rendered = error.to_string();
+        assert!(rendered.contains("--> config.yml:4:1"), "{rendered}");
+        assert!(
+            rendered.contains("4 | databse_url: postgresql"),
+            "{rendered}"
+        );
+    }
+
+    #[test]
+    fn it_points_at_bad_values() {
+        let contents = include_str!("../config.json").replace("1234", "\"abc\"");
+        let error = located(&JsonDeserializer::new(), "config.json", &contents);
+
+        assert_eq!(error.key(), Some("port"));
+        assert_eq!(error.location().map(|l| l.line), Some(2));
+        assert!(error.to_string().contains("^"));
+    }
+
+    #[test]
+    fn it_locates_toml_spans() {
+        let contents = "port = 1234\nbase_url = \"x\"\ns3_path = 5\n";
+        let error = located(&TomlDeserializer::new(), "config.toml", contents);
+
+        assert_eq!(error.location().map(|l| l.line), Some(3));
+        assert_eq!(error.key(), Some("s3_path"));
+    }
+
+    #[test]
+    fn it_has_exit_codes_per_class() {
+        assert_eq!(Error::Usage(String::new()).exit_code(), 2);
+        assert_eq!(
+            Error::Io(std::io::ErrorKind::NotFound.into()).exit_code(),
+            3
+        );
+        assert_eq!(Error::UnknownFormat.exit_code(), 4);
+    }
+}
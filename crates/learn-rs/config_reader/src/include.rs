@@ -0,0 +1,189 @@
+use std::{path::PathBuf, sync::Arc};
+
+use serde_json::Value;
+
+use crate::{
+    ConfigDeserializer,
+    error::{ConfigError, Error},
+    load::read_source,
+    registry::FormatRegistry,
+    source::{self, ConfigSource},
+};
+
+/// Top level key listing the files to pull in, either a single path or a list of them
+pub const INCLUDE_KEY: &str = "include";
+
+/// One config file to load
+pub struct Source {
+    pub name: String,
+    pub contents: String,
+    pub format: Arc<dyn ConfigDeserializer>,
+}
+
+/// `input` and everything it includes, in the order they should be layered: included files come
+/// first, in the order they're listed, so the including file gets the last word. Includes are
+/// followed transitively, with paths relative to the file including them (or to the working
+/// directory for sources that aren't files). An included `http://` URL is fetched as it is.
+pub fn sources(
+    registry: &FormatRegistry,
+    input: &dyn ConfigSource,
+    format_override: Option<Arc<dyn ConfigDeserializer>>,
+) -> Result<Vec<Source>, ConfigError> {
+    let mut sources = Vec::new();
+    collect(
+        registry,
+        input,
+        format_override,
+        &mut Vec::new(),
+        &mut sources,
+    )?;
+    Ok(sources)
+}
+
+fn collect(
+    registry: &FormatRegistry,
+    input: &dyn ConfigSource,
+    format_override: Option<Arc<dyn ConfigDeserializer>>,
+    stack: &mut Vec<PathBuf>,
+    sources: &mut Vec<Source>,
+) -> Result<(), ConfigError> {
+    let name = input.name();
+    let (contents, format) = read_source(registry, input, format_override)?;
+
+    // Only files are followed by path, so only they can be part of a cycle
+    let canonical = match input.path() {
+        None => None,
+        Some(path) => Some(
+            std::fs::canonicalize(path)
+                .map_err(|e| ConfigError::from(Error::Io(e)).with_path(name))?,
+        ),
+    };
+    if let Some(path) = &canonical
+        && let Some(start) = stack.iter().position(|p| p == path)
+    {
+        let cycle: Vec<String> = stack[start..]
+            .iter()
+            .chain([path])
+            .map(|p| p.display().to_string())
+            .collect();
+        let msg = format!("include cycle: {}", cycle.join(" -> "));
+        return Err(ConfigError::from(Error::Invalid(msg)).with_path(name));
+    }
+
+    // Parse errors are reported when the file is loaded for real, with all the context that comes
+    // with that
+    let included = match format.deserialize::<Value>(&contents) {
+        Ok(document) => included(&document).map_err(|e| ConfigError::from(e).with_path(name))?,
+        Err(_) => Vec::new(),
+    };
+    if !included.is_empty() {
+        let base = match input.path() {
+            None => PathBuf::new(),
+            Some(path) => path.parent().map(PathBuf::from).unwrap_or_default(),
+        };
+        let depth = stack.len();
+        stack.extend(canonical);
+        for path in included {
+            let included: Box<dyn ConfigSource> = match source::is_url(&path) {
+                true => Box::new(
+                    source::Http::new(&path).map_err(|e| ConfigError::from(e).with_path(name))?,
+                ),
+                false => Box::new(source::File::new(base.join(path))),
+            };
+            collect(registry, included.as_ref(), None, stack, sources)?;
+        }
+        stack.truncate(depth);
+    }
+
+    sources.push(Source {
+        name: name.to_string(),
+        contents,
+        format,
+    });
+    Ok(())
+}
+
+/// The paths listed under `include`
+fn included(document: &Value) -> Result<Vec<String>, Error> {
+    let invalid = || Error::Invalid(format!("`{INCLUDE_KEY}` must be a path or a list of paths"));
+    match document.get(INCLUDE_KEY) {
+        None => Ok(Vec::new()),
+        Some(Value::String(path)) => Ok(vec![path.clone()]),
+        Some(Value::Array(paths)) => paths
+            .iter()
+            .map(|path| path.as_str().map(str::to_string).ok_or_else(invalid))
+            .collect(),
+        Some(_) => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    /// A fresh directory under the system temp dir for one test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "config_reader_include_{name}_{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("shared")).unwrap();
+        dir
+    }
+
+    fn names(dir: &Path, sources: &[Source]) -> Vec<String> {
+        sources
+            .iter()
+            .map(|s| {
+                let path = Path::new(&s.name);
+                path.strip_prefix(dir).unwrap().display().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_orders_includes_before_the_including_file() {
+        let dir = temp_dir("order");
+        std::fs::write(
+            dir.join("app.yml"),
+            "include: [shared/base.json, local.toml]\nport: 1\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("shared/base.json"),
+            r#"{"include": "common.ini", "port": 2}"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("shared/common.ini"), "port = 3\n").unwrap();
+        std::fs::write(dir.join("local.toml"), "port = 4\n").unwrap();
+
+        let registry = FormatRegistry::default();
+        let app = source::File::new(dir.join("app.yml"));
+        let sources = sources(&registry, &app, None).unwrap();
+        assert_eq!(
+            names(&dir, &sources),
+            [
+                "shared/common.ini",
+                "shared/base.json",
+                "local.toml",
+                "app.yml"
+            ]
+        );
+    }
+
+    #[test]
+    fn it_detects_cycles() {
+        let dir = temp_dir("cycle");
+        std::fs::write(dir.join("a.yml"), "include: shared/b.yml\n").unwrap();
+        std::fs::write(dir.join("shared/b.yml"), "include: ../a.yml\n").unwrap();
+
+        let registry = FormatRegistry::default();
+        let a = source::File::new(dir.join("a.yml"));
+        let err = sources(&registry, &a, None).err().unwrap();
+        assert_eq!(err.exit_code(), 6);
+        assert!(err.to_string().contains("include cycle"), "{err}");
+    }
+}
@@ -0,0 +1,228 @@
+use serde_json::{Map, Value};
+
+use crate::{Error, legacy};
+
+/// Top level section holding values that only exist to be interpolated
+pub const VARS_KEY: &str = "vars";
+
+/// Whether `document` has anything for `resolve` to do, `$${` escapes to unescape included
+pub fn has_references(document: &Value) -> bool {
+    match document {
+        Value::String(s) => {
+            s.contains("$${") || pieces(s).iter().any(|p| !matches!(p, Piece::Text(_)))
+        }
+        Value::Array(values) => values.iter().any(has_references),
+        Value::Object(map) => map.contains_key(VARS_KEY) || map.values().any(has_references),
+        _ => false,
+    }
+}
+
+/// Replace every `${name}` in the strings of `document`, then drop the `vars` section.
+///
+/// `name` is looked up in `vars` first (`${host}` or `${vars.host}`), then as a dotted path into
+/// the document itself, eg. `${database.host}`. A string that is nothing but a reference takes on
+/// the type of what it refers to, so `port: ${default_port}` stays a number. `$${` is a literal
+/// `${`, and references with a `:` in them (`${env:...}`, `${file:...}`) are secret references,
+/// which are left alone.
+pub fn resolve(document: &mut Value) -> Result<(), Error> {
+    let vars = match document
+        .as_object_mut()
+        .and_then(|map| map.remove(VARS_KEY))
+    {
+        Some(Value::Object(vars)) => vars,
+        Some(_) => return Err(Error::Invalid(format!("`{VARS_KEY}` must be a map"))),
+        None => Map::new(),
+    };
+    // References see the document as written, not halfway through being resolved
+    let written = document.clone();
+    let resolver = Resolver {
+        vars: &vars,
+        document: &written,
+    };
+    resolver.walk(document, "")
+}
+
+struct Resolver<'d> {
+    vars: &'d Map<String, Value>,
+    document: &'d Value,
+}
+
+impl Resolver<'_> {
+    fn walk(&self, value: &mut Value, path: &str) -> Result<(), Error> {
+        let child = |key: &str| match path {
+            "" => key.to_string(),
+            _ => format!("{path}.{key}"),
+        };
+        match value {
+            Value::String(s) => {
+                *value = self
+                    .expand(s, &mut Vec::new())
+                    .map_err(|msg| Error::Invalid(format!("in `{path}`: {msg}")))?;
+            }
+            Value::Array(values) => {
+                for (i, value) in values.iter_mut().enumerate() {
+                    self.walk(value, &child(&i.to_string()))?;
+                }
+            }
+            Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    self.walk(value, &child(key))?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// `s` with its references replaced. `stack` holds the names being resolved, to catch cycles.
+    fn expand(&self, s: &str, stack: &mut Vec<String>) -> Result<Value, String> {
+        let pieces = pieces(s);
+        if let [Piece::Ref(name)] = pieces.as_slice() {
+            return self.lookup(name, stack);
+        }
+
+        let mut out = String::new();
+        for piece in pieces {
+            match piece {
+                Piece::Text(text) => out.push_str(text),
+                Piece::Ref(name) => match self.lookup(name, stack)? {
+                    Value::String(s) => out.push_str(&s),
+                    Value::Array(_) | Value::Object(_) => {
+                        return Err(format!("`{name}` can't be interpolated into a string"));
+                    }
+                    other => out.push_str(&other.to_string()),
+                },
+            }
+        }
+        Ok(Value::String(out))
+    }
+
+    fn lookup(&self, name: &str, stack: &mut Vec<String>) -> Result<Value, String> {
+        if stack.iter().any(|n| n == name) {
+            stack.push(name.to_string());
+            return Err(format!("reference cycle: {}", stack.join(" -> ")));
+        }
+        let pointer = format!("/{}", name.replace('.', "/"));
+        let var = name
+            .strip_prefix(VARS_KEY)
+            .and_then(|rest| rest.strip_prefix('.'))
+            .unwrap_or(name);
+        // Documents are nested before they are resolved, so references to keys of the flat
+        // layout have to follow them to where they moved
+        let moved = || legacy::moved_pointer(name).and_then(|p| self.document.pointer(&p));
+        let Some(value) = self
+            .vars
+            .get(var)
+            .or_else(|| self.document.pointer(&pointer))
+            .or_else(moved)
+        else {
+            return Err(format!("unknown variable `{name}`"));
+        };
+
+        let Value::String(s) = value else {
+            return Ok(value.clone());
+        };
+        stack.push(name.to_string());
+        let expanded = self.expand(s, stack);
+        stack.pop();
+        expanded
+    }
+}
+
+enum Piece<'s> {
+    Text(&'s str),
+    Ref(&'s str),
+}
+
+/// Split `s` into literal text and `${name}` references
+fn pieces(s: &str) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        // `$${` escapes the reference
+        if rest[..start].ends_with('$') {
+            pieces.push(Piece::Text(&rest[..start - 1]));
+            pieces.push(Piece::Text("${"));
+            rest = &rest[start + 2..];
+            continue;
+        }
+        let Some(len) = rest[start + 2..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 2..start + 2 + len];
+        let end = start + 2 + len + 1;
+        pieces.push(Piece::Text(&rest[..start]));
+        if name.is_empty() || name.contains(':') {
+            pieces.push(Piece::Text(&rest[start..end]));
+        } else {
+            pieces.push(Piece::Ref(name.trim()));
+        }
+        rest = &rest[end..];
+    }
+    pieces.push(Piece::Text(rest));
+    pieces.retain(|p| !matches!(p, Piece::Text("")));
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn resolved(mut document: Value) -> Result<Value, String> {
+        resolve(&mut document).map_err(|e| e.to_string())?;
+        Ok(document)
+    }
+
+    #[test]
+    fn it_interpolates_vars_and_other_keys() {
+        let document = json!({
+            "vars": {"host": "teach-rs.tweede.golf", "default_port": 1234},
+            "port": "${vars.default_port}",
+            "base_url": "https://config.${host}",
+            "s3_path": "bucket.${host}",
+            "mirror": "${base_url}/mirror",
+        });
+        assert_eq!(
+            resolved(document).unwrap(),
+            json!({
+                "port": 1234,
+                "base_url": "https://config.teach-rs.tweede.golf",
+                "s3_path": "bucket.teach-rs.tweede.golf",
+                "mirror": "https://config.teach-rs.tweede.golf/mirror",
+            })
+        );
+    }
+
+    #[test]
+    fn it_leaves_secret_references_and_escapes_alone() {
+        let document = json!({
+            "database_url": "${env:DATABASE_URL}",
+            "template": "$${literally}",
+        });
+        assert_eq!(
+            resolved(document).unwrap(),
+            json!({"database_url": "${env:DATABASE_URL}", "template": "${literally}"})
+        );
+    }
+
+    #[test]
+    fn it_reports_unknown_variables_and_cycles() {
+        assert_eq!(
+            resolved(json!({"base_url": "https://${host}"})).unwrap_err(),
+            "invalid config: in `base_url`: unknown variable `host`"
+        );
+        assert_eq!(
+            resolved(json!({"vars": {"a": "${b}", "b": "${a}"}, "x": "${a}"})).unwrap_err(),
+            "invalid config: in `x`: reference cycle: a -> b -> a"
+        );
+    }
+
+    #[test]
+    fn it_knows_when_there_is_nothing_to_do() {
+        assert!(!has_references(&json!({"url": "${file:/run/secrets/db}"})));
+        assert!(has_references(&json!({"nested": ["${x}"]})));
+        assert!(has_references(&json!({"vars": {}})));
+        assert!(has_references(&json!({"template": "$${literally}"})));
+    }
+}
@@ -0,0 +1,354 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::{
+    Config, ConfigDeserializer, Error, OwnedConfig, guess_scalar,
+    include::INCLUDE_KEY,
+    interpolate::{self, VARS_KEY},
+    migrate::{self, VERSION_KEY},
+    profile, strict,
+};
+
+/// Environment variables with this prefix override config values, eg. `APP_PORT` -> `port`
+pub const ENV_PREFIX: &str = "APP_";
+
+/// Stacks partial config documents on top of each other. Layers added later take precedence over
+/// earlier ones, so the usual order is base file, environment specific file, local file, and
+/// finally environment variables.
+pub struct LayeredLoader {
+    merged: Value,
+    provenance: BTreeMap<String, String>,
+    strict: bool,
+    profile: Option<String>,
+}
+
+impl LayeredLoader {
+    pub fn new() -> Self {
+        Self {
+            merged: Value::Object(Map::new()),
+            provenance: BTreeMap::new(),
+            strict: false,
+            profile: None,
+        }
+    }
+
+    /// Apply the `profile` section of profiled documents added from now on, on top of their
+    /// `default` section. Documents without profiles are added as they are, whatever the profile,
+    /// so one profile can be selected for a whole deployment.
+    pub fn profile(mut self, profile: Option<String>) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Hold every document added from now on to `strict::check`
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Parse `contents` with `deserializer` and merge it on top of what we have so far
+    pub fn add_contents(
+        mut self,
+        name: &str,
+        deserializer: &dyn ConfigDeserializer,
+        contents: &str,
+    ) -> Result<Self, Error> {
+        if self.strict {
+            strict::check(deserializer, contents)?;
+        }
+        let mut value: Value = deserializer.deserialize(contents)?;
+        let version = migrate::version(&value)?;
+        // Includes are resolved while reading files, see `include::sources`. Variables go in
+        // before the profiles, so profiles can override them too.
+        if let Some(map) = value.as_object_mut() {
+            map.remove(INCLUDE_KEY);
+            map.remove(VERSION_KEY);
+            if let Some(vars) = map.remove(VARS_KEY) {
+                self = self.merge_layer(name, serde_json::json!({ VARS_KEY: vars }));
+            }
+        }
+        for (section, mut value) in profile::layers(value, self.profile.as_deref())? {
+            migrate::apply(&mut value, version);
+            self = match section.is_empty() {
+                true => self.merge_layer(name, value),
+                false => self.merge_layer(&format!("{name} [{section}]"), value),
+            };
+        }
+        Ok(self)
+    }
+
+    /// Merge an already parsed document on top of what we have so far. It has no `version` to
+    /// go by, so it is migrated from the oldest layout, which leaves newer layouts alone. That
+    /// way layers in the flat layout can be layered with nested ones.
+    pub fn add_layer(self, name: &str, mut value: Value) -> Self {
+        migrate::apply(&mut value, 1);
+        self.merge_layer(name, value)
+    }
+
+    fn merge_layer(mut self, name: &str, value: Value) -> Self {
+        merge(&mut self.merged, value, "", name, &mut self.provenance);
+        self
+    }
+
+    /// Resolve `${...}` references in the merged layers, then deserialize them into a `Config`
+    pub fn config(&self) -> Result<OwnedConfig, Error> {
+        let mut merged = self.merged.clone();
+        interpolate::resolve(&mut merged)?;
+        Config::deserialize(merged).map_err(Error::Value)
+    }
+
+    /// Which layer supplied each final value, keyed by dotted path (eg. `database.url`)
+    pub fn provenance(&self) -> &BTreeMap<String, String> {
+        &self.provenance
+    }
+}
+
+impl Default for LayeredLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Build a layer out of the environment variables starting with `prefix`. The prefix is stripped
+/// and the rest lowercased, with a double underscore separating nested keys, so
+/// `APP_DATABASE__URL` becomes `database.url`. Returns `None` when no variable matched.
+pub fn env_layer(prefix: &str, vars: impl IntoIterator<Item = (String, String)>) -> Option<Value> {
+    let mut root = Value::Object(Map::new());
+    let mut found = false;
+
+    for (key, raw) in vars {
+        let Some(key) = key.strip_prefix(prefix) else {
+            continue;
+        };
+        if key.is_empty() {
+            continue;
+        }
+        found = true;
+
+        let mut node = &mut root;
+        let mut parts = key.split("__").map(str::to_lowercase).peekable();
+        while let Some(part) = parts.next() {
+            let Value::Object(map) = node else {
+                break;
+            };
+            if parts.peek().is_none() {
+                map.insert(part, guess_scalar(&raw));
+                break;
+            }
+            node = map.entry(part).or_insert_with(|| Value::Object(Map::new()));
+        }
+    }
+
+    found.then_some(root)
+}
+
+/// Deep merge `layer` into `base`. Objects are merged key by key, anything else replaces what was
+/// there before, and the leaves we end up writing are attributed to `name` in `provenance`.
+fn merge(
+    base: &mut Value,
+    layer: Value,
+    path: &str,
+    name: &str,
+    provenance: &mut BTreeMap<String, String>,
+) {
+    match (base, layer) {
+        (Value::Object(base), Value::Object(layer)) => {
+            for (key, value) in layer {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                let slot = base.entry(key).or_insert(Value::Null);
+                merge(slot, value, &path, name, provenance);
+            }
+        }
+        (base, layer) => {
+            // Whatever used to live at or below this path is gone now
+            let nested = format!("{path}.");
+            provenance.retain(|k, _| k != path && !k.starts_with(&nested));
+            record(&layer, path, name, provenance);
+            *base = layer;
+        }
+    }
+}
+
+fn record(value: &Value, path: &str, name: &str, provenance: &mut BTreeMap<String, String>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                record(value, &format!("{path}.{key}"), name, provenance);
+            }
+        }
+        _ => {
+            provenance.insert(path.to_string(), name.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{JsonDeserializer, YmlDeserializer};
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn it_lets_later_layers_win() {
+        let loader = LayeredLoader::new()
+            .add_contents(
+                "base.yml",
+                &YmlDeserializer::new(),
+                include_str!("../config.yml"),
+            )
+            .unwrap()
+            .add_contents("prod.json", &JsonDeserializer::new(), r#"{"port": 80}"#)
+            .unwrap();
+
+        let config = loader.config().unwrap();
+        assert_eq!(config.port, 80);
+        assert_eq!(config.s3.bucket, "bucket.teach-rs.tweede.golf");
+        assert_eq!(loader.provenance()["port"], "prod.json");
+        assert_eq!(loader.provenance()["s3.bucket"], "base.yml");
+    }
+
+    #[test]
+    fn it_overrides_from_env() {
+        let env = env_layer(
+            ENV_PREFIX,
+            vars(&[
+                ("APP_PORT", "4321"),
+                ("APP_DATABASE_URL", "postgresql://prod@db:5432/db"),
+                ("HOME", "/root"),
+            ]),
+        )
+        .unwrap();
+        let loader = LayeredLoader::new()
+            .add_contents(
+                "base.json",
+                &JsonDeserializer::new(),
+                include_str!("../config.json"),
+            )
+            .unwrap()
+            .add_layer("env", env);
+
+        let config = loader.config().unwrap();
+        assert_eq!(config.port, 4321);
+        assert_eq!(config.database.url.expose(), "postgresql://prod@db:5432/db");
+        assert_eq!(loader.provenance()["database.url"], "env");
+        assert_eq!(loader.provenance()["base_urls"], "base.json");
+        assert!(!loader.provenance().contains_key("home"));
+    }
+
+    #[test]
+    fn it_nests_env_keys() {
+        let env = env_layer(ENV_PREFIX, vars(&[("APP_DATABASE__POOL_SIZE", "8")])).unwrap();
+        assert_eq!(env, serde_json::json!({"database": {"pool_size": 8}}));
+        assert!(env_layer(ENV_PREFIX, vars(&[("PATH", "/bin")])).is_none());
+    }
+
+    #[test]
+    fn it_merges_nested_objects() {
+        let loader = LayeredLoader::new()
+            .add_layer("a", serde_json::json!({"db": {"url": "x", "pool": 1}}))
+            .add_layer("b", serde_json::json!({"db": {"pool": 2}}))
+            .add_layer("c", serde_json::json!({"s3": {"bucket": "y"}}));
+
+        assert_eq!(loader.provenance()["db.url"], "a");
+        assert_eq!(loader.provenance()["db.pool"], "b");
+        assert_eq!(loader.provenance()["s3.bucket"], "c");
+
+        let loader = loader.add_layer("d", serde_json::json!({"db": "flat"}));
+        assert_eq!(loader.provenance()["db"], "d");
+        assert!(!loader.provenance().contains_key("db.url"));
+    }
+
+    #[test]
+    fn it_layers_nested_sections_over_the_flat_layout() {
+        let loader = LayeredLoader::new()
+            .add_contents(
+                "base.yml",
+                &YmlDeserializer::new(),
+                include_str!("../config.yml"),
+            )
+            .unwrap()
+            .add_layer("local", serde_json::json!({"database": {"pool_size": 2}}));
+
+        let config = loader.config().unwrap();
+        assert_eq!(config.database.pool_size, 2);
+        assert_eq!(
+            config.database.url.expose(),
+            "postgresql://user@database:5432/db"
+        );
+        assert_eq!(loader.provenance()["database.url"], "base.yml");
+        assert_eq!(loader.provenance()["database.pool_size"], "local");
+    }
+
+    #[test]
+    fn it_resolves_references_to_flat_keys() {
+        let yaml = "port: 1234\n\
+                    base_url: https://${s3_path}\n\
+                    s3_path: bucket.teach-rs.tweede.golf\n\
+                    database_url: postgresql://user@database:5432/db?app=${base_url}\n";
+        let config = LayeredLoader::new()
+            .add_contents("config.yml", &YmlDeserializer::new(), yaml)
+            .unwrap()
+            .config()
+            .unwrap();
+        assert_eq!(config.base_url(), "https://bucket.teach-rs.tweede.golf");
+        assert_eq!(
+            config.database.url.expose(),
+            "postgresql://user@database:5432/db?app=https://bucket.teach-rs.tweede.golf"
+        );
+    }
+
+    #[test]
+    fn it_resolves_profiles_the_same_in_every_format() {
+        let json = r#"{
+            "default": {
+                "port": 1234,
+                "base_url": "https://example.com",
+                "s3_path": "bucket",
+                "database_url": "postgresql://db/app"
+            },
+            "prod": {"port": 443, "database_url": "postgresql://prod-db/app"}
+        }"#;
+        let yaml = "default:\n  port: 1234\n  base_url: https://example.com\n  s3_path: bucket\n  \
+                    database_url: postgresql://db/app\n\
+                    prod:\n  port: 443\n  database_url: postgresql://prod-db/app\n";
+
+        let load = |format: &dyn ConfigDeserializer, contents| {
+            LayeredLoader::new()
+                .profile(Some("prod".to_string()))
+                .add_contents("config", format, contents)
+                .unwrap()
+        };
+        let from_json = load(&JsonDeserializer::new(), json);
+        let from_yaml = load(&YmlDeserializer::new(), yaml);
+        let config = from_json.config().unwrap();
+        assert_eq!(config, from_yaml.config().unwrap());
+        assert_eq!(config.port, 443);
+        assert_eq!(config.base_url(), "https://example.com");
+        assert_eq!(from_json.provenance()["port"], "config [prod]");
+        assert_eq!(from_json.provenance()["s3.bucket"], "config [default]");
+    }
+
+    #[test]
+    fn it_lists_available_profiles() {
+        let yaml = "default:\n  port: 1\ndev:\n  port: 2\nprod:\n  port: 3\n";
+        let result = LayeredLoader::new()
+            .profile(Some("staging".to_string()))
+            .add_contents("config", &YmlDeserializer::new(), yaml);
+        assert!(matches!(
+            result,
+            Err(Error::UnknownProfile { available, .. }) if available == ["dev", "prod"]
+        ));
+    }
+}
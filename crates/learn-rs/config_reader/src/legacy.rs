@@ -0,0 +1,192 @@
+use std::{borrow::Cow, fmt, marker::PhantomData};
+
+use serde::{
+    Deserialize, Deserializer,
+    de::{
+        self, Visitor,
+        value::{
+            BorrowedStrDeserializer, MapAccessDeserializer, MapDeserializer, SeqAccessDeserializer,
+        },
+    },
+};
+use serde_json::{Map, Value};
+
+use crate::{Database, S3};
+
+/// Keys of the flat layout configs used to have, where they live now, and which field of that
+/// section they are (`None` for lists)
+const MOVED: [(&str, &str, Option<&str>); 3] = [
+    ("base_url", "base_urls", None),
+    ("database_url", "database", Some("url")),
+    ("s3_path", "s3", Some("bucket")),
+];
+
+/// Rewrite the flat layout (`database_url: ...`) and shorthands (`database: <url>`,
+/// `base_urls: <url>`) in `document` to the nested layout, so documents can be merged and
+/// checked against the schema key by key. A key set in both layouts is left for deserializing
+/// to complain about.
+///
+/// `Config` itself accepts both layouts too, this is for documents that aren't deserialized
+/// straight into one.
+pub fn nest(document: &mut Value) {
+    let Value::Object(map) = document else {
+        return;
+    };
+    for (old, new, field) in MOVED {
+        if map.contains_key(old) && map.contains_key(new) {
+            continue;
+        }
+        // Renamed where it stands, so rewritten files keep their order
+        if map.contains_key(old) {
+            *map = std::mem::take(map)
+                .into_iter()
+                .map(|(key, value)| match key == old {
+                    true => (new.to_string(), value),
+                    false => (key, value),
+                })
+                .collect();
+        }
+        let Some(value) = map.get_mut(new) else {
+            continue;
+        };
+        match field {
+            None if !value.is_array() => *value = Value::Array(vec![value.take()]),
+            Some(field) if !value.is_object() => {
+                *value = Value::Object(Map::from_iter([(field.to_string(), value.take())]));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Where `old`, a key of the flat layout, lives now
+pub(crate) fn renamed(old: &str) -> Option<&'static str> {
+    MOVED
+        .iter()
+        .find(|(name, ..)| *name == old)
+        .map(|(_, new, _)| *new)
+}
+
+/// Where the value `old`, a key of the flat layout, lives after `nest`, as a JSON pointer. A
+/// flat key held a single value, which ends up first in lists.
+pub(crate) fn moved_pointer(old: &str) -> Option<String> {
+    let (_, new, field) = MOVED.iter().find(|(name, ..)| *name == old)?;
+    Some(match field {
+        Some(field) => format!("/{new}/{field}"),
+        None => format!("/{new}/0"),
+    })
+}
+
+/// `deserialize_with` for `base_urls`, which takes a single URL as well as a list of them
+pub(crate) fn base_urls<'de: 'a, 'a, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Cow<'a, str>>, D::Error> {
+    struct OneOrMany;
+
+    impl<'de> Visitor<'de> for OneOrMany {
+        type Value = Vec<Cow<'de, str>>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a URL or a list of URLs")
+        }
+
+        fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> Result<Self::Value, E> {
+            Ok(vec![Cow::Borrowed(v)])
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            Ok(vec![Cow::Owned(v.to_string())])
+        }
+
+        fn visit_seq<A: de::SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+            Deserialize::deserialize(SeqAccessDeserializer::new(seq))
+        }
+    }
+
+    deserializer.deserialize_any(OneOrMany)
+}
+
+/// `deserialize_with` for `database`, which takes just the URL as well
+pub(crate) fn database<'de: 'a, 'a, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Database<'a>, D::Error> {
+    shorthand("url", deserializer)
+}
+
+/// `deserialize_with` for `s3`, which takes just the bucket as well
+pub(crate) fn s3<'de: 'a, 'a, D: Deserializer<'de>>(deserializer: D) -> Result<S3<'a>, D::Error> {
+    shorthand("bucket", deserializer)
+}
+
+/// Deserialize a section, where a string `s` stands for `{field: s}`
+fn shorthand<'de, D: Deserializer<'de>, T: Deserialize<'de>>(
+    field: &'static str,
+    deserializer: D,
+) -> Result<T, D::Error> {
+    struct Shorthand<T>(&'static str, PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de>> Visitor<'de> for Shorthand<T> {
+        type Value = T;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a map, or a string for its `{}`", self.0)
+        }
+
+        fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> Result<T, E> {
+            let entry = (self.0, BorrowedStrDeserializer::new(v));
+            T::deserialize(MapDeserializer::new(std::iter::once(entry)))
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<T, E> {
+            let entry = (self.0, v.to_string());
+            T::deserialize(MapDeserializer::new(std::iter::once(entry)))
+        }
+
+        fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<T, A::Error> {
+            T::deserialize(MapAccessDeserializer::new(map))
+        }
+    }
+
+    deserializer.deserialize_any(Shorthand(field, PhantomData))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn it_nests_the_flat_layout() {
+        let mut document = json!({
+            "port": 1234,
+            "base_url": "https://example.com",
+            "s3_path": "bucket",
+            "database_url": "postgresql://db/app",
+        });
+        nest(&mut document);
+        assert_eq!(
+            document,
+            json!({
+                "port": 1234,
+                "base_urls": ["https://example.com"],
+                "s3": {"bucket": "bucket"},
+                "database": {"url": "postgresql://db/app"},
+            })
+        );
+    }
+
+    #[test]
+    fn it_leaves_the_nested_layout_alone() {
+        let nested = json!({
+            "base_urls": ["https://a.example", "https://b.example"],
+            "database": {"pool_size": 8},
+        });
+        let mut document = nested.clone();
+        nest(&mut document);
+        assert_eq!(document, nested);
+
+        let mut both = json!({"database_url": "postgresql://db/app", "database": {}});
+        nest(&mut both);
+        assert!(both.get("database_url").is_some());
+    }
+}
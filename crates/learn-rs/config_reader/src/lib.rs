@@ -0,0 +1,910 @@
+//! Reads an imaginary config file from JSON, YAML, TOML or INI.
+//!
+//! [`Config`] is the config itself, [`ConfigDeserializer`] is implemented once per format and
+//! [`FormatRegistry`] picks one for a file. [`load_config`] does everything the
+//! `learn_rs_config_reader` binary does before printing: includes, layering, profiles,
+//! environment overrides, interpolation and validation.
+
+use std::{borrow::Cow, collections::BTreeMap, fmt, time::Duration};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+pub mod diff;
+pub mod edit;
+pub mod error;
+pub mod include;
+pub mod interpolate;
+pub mod layered;
+pub mod legacy;
+pub mod lint;
+mod load;
+pub mod migrate;
+pub mod profile;
+pub mod query;
+pub mod registry;
+pub mod schema;
+pub mod secret;
+pub mod source;
+pub mod strict;
+pub mod units;
+pub mod watch;
+
+pub use error::{ConfigError, Error, Location};
+pub use layered::LayeredLoader;
+pub use load::{LoadOptions, Loaded, load_config, load_sources, read_input, read_source};
+pub use registry::FormatRegistry;
+pub use secret::Secret;
+pub use source::ConfigSource;
+pub use units::ByteSize;
+pub use watch::{ConfigWatcher, ReloadEvent};
+
+use registry::first_line;
+
+/// Deserialize `contents` into any `T` with `deserializer`
+pub fn deserialize_config<'a, T: Deserialize<'a>>(
+    deserializer: &dyn ConfigDeserializer,
+    contents: &'a str,
+) -> Result<T, Error> {
+    deserializer.deserialize(contents)
+}
+
+/// An imaginary config file
+///
+/// String fields are `Cow`s so formats that can hand out borrowed strings (JSON, YAML) stay
+/// zero-copy, while formats that can't (TOML, INI) fall back to owned strings.
+///
+/// The flat layout configs used to have (`base_url`, `database_url`, `s3_path`) still
+/// deserializes, see `legacy`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq)]
+#[schemars(description = "An imaginary config file")]
+pub struct Config<'a> {
+    #[serde(deserialize_with = "strict::lenient_number")]
+    pub port: u16,
+    /// Where the app can be reached, the canonical URL first
+    #[serde(borrow, alias = "base_url", deserialize_with = "legacy::base_urls")]
+    #[schemars(length(min = 1), inner(url))]
+    pub base_urls: Vec<Cow<'a, str>>,
+    #[serde(borrow, alias = "database_url", deserialize_with = "legacy::database")]
+    pub database: Database<'a>,
+    #[serde(borrow, alias = "s3_path", deserialize_with = "legacy::s3")]
+    pub s3: S3<'a>,
+    /// Feature flags, by name
+    #[serde(default)]
+    pub features: BTreeMap<String, bool>,
+    #[serde(default)]
+    pub limits: Limits,
+}
+
+/// Where the app keeps its data
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq)]
+pub struct Database<'a> {
+    /// Holds credentials, so it's kept out of `Debug` output
+    #[schemars(
+        description = "Database connection string, or a `${file:...}`/`${env:...}` reference"
+    )]
+    pub url: Secret<Cow<'a, str>>,
+    #[serde(
+        default = "default_pool_size",
+        deserialize_with = "strict::lenient_number"
+    )]
+    #[schemars(range(min = 1))]
+    pub pool_size: u32,
+    /// How long to wait for a connection
+    #[serde(default = "default_timeout", with = "units::duration")]
+    #[schemars(schema_with = "units::duration::schema")]
+    pub timeout: Duration,
+}
+
+fn default_pool_size() -> u32 {
+    10
+}
+
+fn default_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+/// Where the app keeps its files
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq)]
+pub struct S3<'a> {
+    #[serde(borrow)]
+    pub bucket: Cow<'a, str>,
+    /// Left to the S3 client when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<Cow<'a, str>>,
+}
+
+/// How much the app takes on at once
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Limits {
+    /// How long a request may take before it's cancelled
+    #[serde(with = "units::duration")]
+    #[schemars(schema_with = "units::duration::schema")]
+    pub request_timeout: Duration,
+    /// The largest request body that is accepted
+    pub max_body_size: ByteSize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            request_timeout: Duration::from_secs(30),
+            max_body_size: ByteSize::mib(10),
+        }
+    }
+}
+
+/// A `Config` that doesn't borrow from the contents it was parsed from, so it can be kept around
+/// and swapped out at runtime
+pub type OwnedConfig = Config<'static>;
+
+impl Config<'_> {
+    /// Detach from the contents this config was parsed from
+    pub fn into_owned(self) -> OwnedConfig {
+        let owned = |s: Cow<'_, str>| Cow::Owned(s.into_owned());
+        Config {
+            port: self.port,
+            base_urls: self.base_urls.into_iter().map(owned).collect(),
+            database: Database {
+                url: self.database.url.map(owned),
+                pool_size: self.database.pool_size,
+                timeout: self.database.timeout,
+            },
+            s3: S3 {
+                bucket: owned(self.s3.bucket),
+                region: self.s3.region.map(owned),
+            },
+            features: self.features,
+            limits: self.limits,
+        }
+    }
+
+    /// The canonical URL the app can be reached at
+    pub fn base_url(&self) -> &str {
+        self.base_urls.first().map_or("", |url| url)
+    }
+
+    /// The config as a tree, for printing. Secrets are `[REDACTED]` unless `reveal` is set, in
+    /// which case they're in plain text rather than the reference they were loaded from.
+    pub fn to_value(&self, reveal: bool) -> Result<serde_json::Value, Error> {
+        let mut value = serde_json::to_value(self).map_err(Error::Value)?;
+        value["database"]["url"] = match reveal {
+            true => self.database.url.expose().as_ref().into(),
+            false => "[REDACTED]".into(),
+        };
+        Ok(value)
+    }
+
+    /// `Debug`s like the config itself, but with secrets in plain text
+    pub fn revealed(&self) -> impl fmt::Debug + '_ {
+        struct Revealed<'c, 'a>(&'c Config<'a>);
+        struct RevealedDatabase<'c, 'a>(&'c Database<'a>);
+
+        impl fmt::Debug for Revealed<'_, '_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("Config")
+                    .field("port", &self.0.port)
+                    .field("base_urls", &self.0.base_urls)
+                    .field("database", &RevealedDatabase(&self.0.database))
+                    .field("s3", &self.0.s3)
+                    .field("features", &self.0.features)
+                    .field("limits", &self.0.limits)
+                    .finish()
+            }
+        }
+
+        impl fmt::Debug for RevealedDatabase<'_, '_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("Database")
+                    .field("url", self.0.url.expose())
+                    .field("pool_size", &self.0.pool_size)
+                    .field("timeout", &self.0.timeout)
+                    .finish()
+            }
+        }
+
+        Revealed(self)
+    }
+
+    /// Sanity checks that go beyond what the types can express
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.port == 0 {
+            return Err(Error::Invalid("`port` must not be 0".to_string()));
+        }
+        if self.base_urls.is_empty() {
+            return Err(Error::Invalid("`base_urls` must not be empty".to_string()));
+        }
+        if let Some(url) = self.base_urls.iter().find(|url| !url.contains("://")) {
+            let msg = format!("`base_urls` must only hold URLs, got `{url}`");
+            return Err(Error::Invalid(msg));
+        }
+        // Don't echo the value back, it's a secret
+        if !self.database.url.expose().contains("://") {
+            return Err(Error::Invalid("`database.url` must be a URL".to_string()));
+        }
+        if self.database.pool_size == 0 {
+            return Err(Error::Invalid(
+                "`database.pool_size` must not be 0".to_string(),
+            ));
+        }
+        if self.s3.bucket.is_empty() {
+            return Err(Error::Invalid("`s3.bucket` must not be empty".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Hands whatever is being deserialized a type-erased `Deserializer` over the config contents
+pub type Visit<'de, 'v> =
+    &'v mut dyn FnMut(&mut dyn erased_serde::Deserializer<'de>) -> Result<(), erased_serde::Error>;
+
+// Had to rename this bc I didn't like the original name..
+// Original name = `DeserializeConfig`
+pub trait ConfigDeserializer: Send + Sync {
+    /// What the format is called, eg. for `--format`
+    fn name(&self) -> &'static str;
+
+    /// File extensions, without the leading `.`
+    fn extensions(&self) -> &'static [&'static str];
+
+    fn mime_types(&self) -> &'static [&'static str];
+
+    /// Whether `contents` look like they're in this format. Formats that can't tell should leave
+    /// this alone.
+    fn sniff(&self, _contents: &str) -> bool {
+        false
+    }
+
+    /// Write `value` out in this format. Formats that can only be read should leave this alone.
+    fn serialize(
+        &self,
+        _value: &dyn erased_serde::Serialize,
+        _pretty: bool,
+    ) -> Result<String, Error> {
+        Err(Error::Unsupported(format!(
+            "{} can't be written",
+            self.name()
+        )))
+    }
+
+    /// Set the value at `path` in `contents`, keeping everything else as it was written. Use
+    /// `edit::set`, which checks the result. Formats that can't be edited in place should leave
+    /// this alone.
+    fn set(
+        &self,
+        _contents: &str,
+        _path: &str,
+        _value: &serde_json::Value,
+    ) -> Result<String, Error> {
+        Err(Error::Unsupported(format!(
+            "{} can't be edited in place",
+            self.name()
+        )))
+    }
+
+    /// Call `visit` with a deserializer over the contents.
+    ///
+    /// Generic methods would make this trait unusable as a trait object, so implementations only
+    /// deal in erased deserializers. Use `<dyn ConfigDeserializer>::deserialize` to get a typed
+    /// value out.
+    fn deserialize_erased<'de>(
+        &self,
+        contents: &'de str,
+        visit: Visit<'de, '_>,
+    ) -> Result<(), Error>;
+}
+
+impl dyn ConfigDeserializer + '_ {
+    /// Deserialize the contents into any `T`, a `Config` or something else entirely
+    pub fn deserialize<'de, T: Deserialize<'de>>(&self, contents: &'de str) -> Result<T, Error> {
+        let mut result = None;
+        self.deserialize_erased(contents, &mut |de| {
+            result = Some(erased_serde::deserialize(de)?);
+            Ok(())
+        })?;
+        Ok(result.expect("ConfigDeserializer implementations must call `visit`"))
+    }
+}
+
+#[derive(Default)]
+pub struct JsonDeserializer {}
+
+impl JsonDeserializer {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl ConfigDeserializer for JsonDeserializer {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["json"]
+    }
+
+    fn mime_types(&self) -> &'static [&'static str] {
+        &["application/json"]
+    }
+
+    fn sniff(&self, contents: &str) -> bool {
+        first_line(contents).is_some_and(|line| line.starts_with('{'))
+    }
+
+    fn serialize(
+        &self,
+        value: &dyn erased_serde::Serialize,
+        pretty: bool,
+    ) -> Result<String, Error> {
+        let result = match pretty {
+            true => serde_json::to_string_pretty(value),
+            false => serde_json::to_string(value),
+        };
+        // Files should end with a newline
+        result
+            .map(|s| s + "\n")
+            .map_err(|e| Error::Serialize(e.to_string()))
+    }
+
+    fn set(&self, contents: &str, path: &str, value: &serde_json::Value) -> Result<String, Error> {
+        edit::json(contents, path, value)
+    }
+
+    fn deserialize_erased<'de>(
+        &self,
+        contents: &'de str,
+        visit: Visit<'de, '_>,
+    ) -> Result<(), Error> {
+        let mut de = serde_json::Deserializer::from_str(contents);
+        match visit(&mut <dyn erased_serde::Deserializer>::erase(&mut de)) {
+            Ok(()) => de.end().map_err(Error::Json),
+            Err(e) => Err(Error::Json(serde::de::Error::custom(e))),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct YmlDeserializer {}
+
+impl YmlDeserializer {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl ConfigDeserializer for YmlDeserializer {
+    fn name(&self) -> &'static str {
+        "yaml"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["yaml", "yml"]
+    }
+
+    fn mime_types(&self) -> &'static [&'static str] {
+        &["application/yaml", "application/x-yaml", "text/yaml"]
+    }
+
+    fn sniff(&self, contents: &str) -> bool {
+        let Some(line) = first_line(contents) else {
+            return false;
+        };
+        // `key: value`, as long as it isn't TOML or INI with a `:` in the value
+        let is_mapping = line
+            .find(':')
+            .is_some_and(|colon| !line[..colon].contains('='));
+        line.starts_with("---") || line.starts_with("%YAML") || is_mapping
+    }
+
+    /// YAML only comes in block style, so `pretty` makes no difference
+    fn serialize(
+        &self,
+        value: &dyn erased_serde::Serialize,
+        _pretty: bool,
+    ) -> Result<String, Error> {
+        serde_yaml::to_string(value).map_err(|e| Error::Serialize(e.to_string()))
+    }
+
+    fn set(&self, contents: &str, path: &str, value: &serde_json::Value) -> Result<String, Error> {
+        edit::yaml(contents, path, value)
+    }
+
+    fn deserialize_erased<'de>(
+        &self,
+        contents: &'de str,
+        visit: Visit<'de, '_>,
+    ) -> Result<(), Error> {
+        let de = serde_yaml::Deserializer::from_str(contents);
+        match visit(&mut <dyn erased_serde::Deserializer>::erase(de)) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(Error::Yaml(serde::de::Error::custom(e))),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct TomlDeserializer {}
+
+impl TomlDeserializer {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl ConfigDeserializer for TomlDeserializer {
+    fn name(&self) -> &'static str {
+        "toml"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["toml"]
+    }
+
+    fn mime_types(&self) -> &'static [&'static str] {
+        &["application/toml"]
+    }
+
+    fn sniff(&self, contents: &str) -> bool {
+        // Looks the same as INI up front, so see if the whole thing actually parses
+        first_line(contents).is_some_and(|line| line.starts_with('[') || line.contains('='))
+            && contents.parse::<toml::Table>().is_ok()
+    }
+
+    fn serialize(
+        &self,
+        value: &dyn erased_serde::Serialize,
+        pretty: bool,
+    ) -> Result<String, Error> {
+        let result = match pretty {
+            true => toml::to_string_pretty(value),
+            false => toml::to_string(value),
+        };
+        result.map_err(|e| Error::Serialize(e.to_string()))
+    }
+
+    fn deserialize_erased<'de>(
+        &self,
+        contents: &'de str,
+        visit: Visit<'de, '_>,
+    ) -> Result<(), Error> {
+        let de = toml::Deserializer::new(contents);
+        match visit(&mut <dyn erased_serde::Deserializer>::erase(de)) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(Error::Toml(serde::de::Error::custom(e))),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct IniDeserializer {}
+
+impl IniDeserializer {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /// INI has no serde support of its own, so we go through a tree
+    fn parse_tree(contents: &str) -> Result<IniNode, Error> {
+        // Quotes are dealt with in `ini_value`, so quoted values can stay strings
+        let options = ini::ParseOption {
+            enabled_quote: false,
+            ..Default::default()
+        };
+        let ini = match ini::Ini::load_from_str_opt(contents, options) {
+            Ok(ini) => ini,
+            Err(e) => return Err(Error::Ini(e)),
+        };
+
+        // Keys outside of any section live at the top level, named sections become nested maps.
+        let mut root = Vec::new();
+        for (section, properties) in ini.iter() {
+            let entries = properties
+                .iter()
+                .map(|(k, v)| (k.to_string(), IniNode::Scalar(ini_value(v))));
+            match section {
+                None => root.extend(entries),
+                Some(name) => root.push((name.to_string(), IniNode::Section(entries.collect()))),
+            }
+        }
+        Ok(IniNode::Section(root))
+    }
+}
+
+/// A parsed INI document. Unlike a `serde_json::Value` this keeps every occurrence of a repeated
+/// key, so serde sees duplicates the same way it does for the other formats.
+enum IniNode {
+    Scalar(serde_json::Value),
+    Section(Vec<(String, IniNode)>),
+}
+
+impl<'de> serde::de::IntoDeserializer<'de, serde_json::Error> for IniNode {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> serde::Deserializer<'de> for IniNode {
+    type Error = serde_json::Error;
+
+    fn deserialize_any<V: serde::de::Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self {
+            IniNode::Scalar(value) => value.deserialize_any(visitor),
+            IniNode::Section(entries) => {
+                let mut map = serde::de::value::MapDeserializer::new(entries.into_iter());
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+        }
+    }
+
+    fn deserialize_option<V: serde::de::Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self {
+            IniNode::Scalar(value) => value.deserialize_option(visitor),
+            section => visitor.visit_some(section),
+        }
+    }
+
+    fn deserialize_enum<V: serde::de::Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self {
+            IniNode::Scalar(value) => value.deserialize_enum(name, variants, visitor),
+            section => section.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: serde::de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+impl ConfigDeserializer for IniDeserializer {
+    fn name(&self) -> &'static str {
+        "ini"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["ini"]
+    }
+
+    fn mime_types(&self) -> &'static [&'static str] {
+        &["application/x-ini"]
+    }
+
+    fn sniff(&self, contents: &str) -> bool {
+        first_line(contents).is_some_and(|line| line.starts_with('[') || line.contains('='))
+    }
+
+    /// Top level values go first, then a section per nested map. INI has no way of writing
+    /// anything nested deeper than that, or lists of more than one value.
+    fn serialize(
+        &self,
+        value: &dyn erased_serde::Serialize,
+        pretty: bool,
+    ) -> Result<String, Error> {
+        let value = serde_json::to_value(value).map_err(|e| Error::Serialize(e.to_string()))?;
+        let serde_json::Value::Object(root) = value else {
+            return Err(Error::Serialize("INI can only hold maps".to_string()));
+        };
+
+        let mut ini = ini::Ini::new();
+        for (key, value) in &root {
+            match value {
+                serde_json::Value::Object(section) => {
+                    for (k, v) in section {
+                        ini.with_section(Some(key.as_str())).set(k, ini_scalar(v)?);
+                    }
+                }
+                _ => {
+                    ini.with_general_section().set(key, ini_scalar(value)?);
+                }
+            }
+        }
+
+        let options = ini::WriteOption {
+            kv_separator: if pretty { " = " } else { "=" },
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        ini.write_to_opt(&mut out, options)
+            .map_err(|e| Error::Serialize(e.to_string()))?;
+        String::from_utf8(out).map_err(|e| Error::Serialize(e.to_string()))
+    }
+
+    fn deserialize_erased<'de>(
+        &self,
+        contents: &'de str,
+        visit: Visit<'de, '_>,
+    ) -> Result<(), Error> {
+        let tree = Self::parse_tree(contents)?;
+        match visit(&mut <dyn erased_serde::Deserializer>::erase(tree)) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(Error::Value(serde::de::Error::custom(e))),
+        }
+    }
+}
+
+fn ini_scalar(value: &serde_json::Value) -> Result<String, Error> {
+    match value {
+        serde_json::Value::String(s) => Ok(s.clone()),
+        serde_json::Value::Number(n) => Ok(n.to_string()),
+        serde_json::Value::Bool(b) => Ok(b.to_string()),
+        serde_json::Value::Null => Ok(String::new()),
+        // A list of one is written as just the value, which `Config` takes for lists too
+        serde_json::Value::Array(items) if items.len() == 1 => ini_scalar(&items[0]),
+        _ => Err(Error::Serialize(format!("INI can't hold `{value}`"))),
+    }
+}
+
+/// A raw INI value. Quoting a value keeps it a string, anything else is up for `guess_scalar`.
+fn ini_value(raw: &str) -> serde_json::Value {
+    let quoted = raw.len() >= 2
+        && ((raw.starts_with('"') && raw.ends_with('"'))
+            || (raw.starts_with('\'') && raw.ends_with('\'')));
+    match quoted {
+        true => raw[1..raw.len() - 1].into(),
+        false => guess_scalar(raw),
+    }
+}
+
+/// Untyped sources (INI, environment variables) hand us every value as a string. Guess at the
+/// intended type so fields like `port` can still be deserialized into numbers.
+pub(crate) fn guess_scalar(raw: &str) -> serde_json::Value {
+    if let Ok(n) = raw.parse::<i64>() {
+        n.into()
+    } else if let Ok(f) = raw.parse::<f64>() {
+        f.into()
+    } else if let Ok(b) = raw.parse::<bool>() {
+        b.into()
+    } else {
+        raw.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JSON: &str = include_str!("../config.json");
+    const YAML: &str = include_str!("../config.yml");
+    const TOML: &str = include_str!("../config.toml");
+    const INI: &str = include_str!("../config.ini");
+
+    fn expected() -> Config<'static> {
+        Config {
+            port: 1234,
+            base_urls: vec!["https://config.teach-rs.tweede.golf".into()],
+            database: Database {
+                url: Secret::from(Cow::from("postgresql://user@database:5432/db")),
+                pool_size: 10,
+                timeout: Duration::from_secs(30),
+            },
+            s3: S3 {
+                bucket: "bucket.teach-rs.tweede.golf".into(),
+                region: None,
+            },
+            features: BTreeMap::new(),
+            limits: Limits::default(),
+        }
+    }
+
+    #[test]
+    fn it_deserializes_json() {
+        let config: Config = deserialize_config(&JsonDeserializer::new(), JSON).unwrap();
+        assert_eq!(config, expected());
+    }
+
+    #[test]
+    fn it_deserializes_yaml() {
+        let config: Config = deserialize_config(&YmlDeserializer::new(), YAML).unwrap();
+        assert_eq!(config, expected());
+    }
+
+    #[test]
+    fn it_deserializes_toml() {
+        let config: Config = deserialize_config(&TomlDeserializer::new(), TOML).unwrap();
+        assert_eq!(config, expected());
+    }
+
+    #[test]
+    fn it_deserializes_ini() {
+        let config: Config = deserialize_config(&IniDeserializer::new(), INI).unwrap();
+        assert_eq!(config, expected());
+    }
+
+    #[test]
+    fn it_borrows_from_json() {
+        let config: Config = deserialize_config(&JsonDeserializer::new(), JSON).unwrap();
+        assert!(matches!(config.base_urls[0], Cow::Borrowed(_)));
+        assert!(matches!(config.s3.bucket, Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn it_reports_format_errors() {
+        assert!(matches!(
+            deserialize_config::<Config>(&TomlDeserializer::new(), "port = \"nope\""),
+            Err(Error::Toml(_))
+        ));
+        assert!(matches!(
+            deserialize_config::<Config>(&IniDeserializer::new(), "port = nope"),
+            Err(Error::Value(_))
+        ));
+    }
+
+    #[test]
+    fn it_deserializes_nested_sections() {
+        let yaml = "port: 1234\n\
+                    base_urls:\n  - https://a.example\n  - https://b.example\n\
+                    database:\n  url: postgresql://db/app\n  pool_size: 4\n  timeout: 1m30s\n\
+                    s3:\n  bucket: files\n  region: eu-west-1\n\
+                    features:\n  beta: true\n\
+                    limits:\n  max_body_size: 1MiB\n";
+        let toml = "port = 1234\n\
+                    base_urls = [\"https://a.example\", \"https://b.example\"]\n\
+                    [database]\nurl = \"postgresql://db/app\"\npool_size = 4\ntimeout = \"1m30s\"\n\
+                    [s3]\nbucket = \"files\"\nregion = \"eu-west-1\"\n\
+                    [features]\nbeta = true\n\
+                    [limits]\nmax_body_size = 1048576\n";
+
+        let config: Config = deserialize_config(&YmlDeserializer::new(), yaml).unwrap();
+        assert_eq!(config.base_url(), "https://a.example");
+        assert_eq!(config.base_urls.len(), 2);
+        assert_eq!(config.database.pool_size, 4);
+        assert_eq!(config.database.timeout, Duration::from_secs(90));
+        assert_eq!(config.s3.region.as_deref(), Some("eu-west-1"));
+        assert!(config.features["beta"]);
+        assert_eq!(config.limits.max_body_size, ByteSize::mib(1));
+        assert_eq!(config.limits.request_timeout, Duration::from_secs(30));
+        let from_toml: Config = deserialize_config(&TomlDeserializer::new(), toml).unwrap();
+        assert_eq!(from_toml, config);
+    }
+
+    #[test]
+    fn it_refuses_both_layouts_at_once() {
+        let json = r#"{
+            "port": 1234,
+            "base_url": "https://example.com",
+            "s3": "bucket",
+            "database_url": "postgresql://db/app",
+            "database": {"url": "postgresql://db/other"}
+        }"#;
+        let err = deserialize_config::<Config>(&JsonDeserializer::new(), json).unwrap_err();
+        assert!(
+            err.to_string().contains("duplicate field `database`"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn it_deserializes_other_types() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Service {
+            name: String,
+            replicas: u8,
+        }
+
+        let registry = FormatRegistry::default();
+        let formats = [
+            ("json", r#"{"name": "api", "replicas": 3}"#),
+            ("yaml", "name: api\nreplicas: 3\n"),
+            ("toml", "name = \"api\"\nreplicas = 3\n"),
+            ("ini", "name = api\nreplicas = 3\n"),
+        ];
+        for (format, contents) in formats {
+            let service: Service = registry
+                .by_name(format)
+                .unwrap()
+                .deserialize(contents)
+                .unwrap();
+            let expected = Service {
+                name: "api".to_string(),
+                replicas: 3,
+            };
+            assert_eq!(service, expected, "{format:?}");
+        }
+    }
+
+    #[test]
+    fn it_deserializes_borrowed_types() {
+        #[derive(Deserialize)]
+        struct Urls<'a> {
+            base_url: &'a str,
+        }
+
+        let urls: Urls = deserialize_config(&JsonDeserializer::new(), JSON).unwrap();
+        assert_eq!(urls.base_url, "https://config.teach-rs.tweede.golf");
+    }
+
+    #[test]
+    fn it_round_trips_between_every_format() {
+        let registry = FormatRegistry::default();
+        let fixtures = [("json", JSON), ("yaml", YAML), ("toml", TOML), ("ini", INI)];
+
+        for (from, contents) in fixtures {
+            let config: Config = registry
+                .by_name(from)
+                .unwrap()
+                .deserialize(contents)
+                .unwrap();
+            for to in registry.formats() {
+                for pretty in [true, false] {
+                    let converted = to.serialize(&config, pretty).unwrap();
+                    let back: Config = to.deserialize(&converted).unwrap();
+                    assert_eq!(
+                        back,
+                        expected(),
+                        "{from} -> {} (pretty: {pretty})",
+                        to.name()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn it_writes_compact_json() {
+        let json = JsonDeserializer::new()
+            .serialize(&expected(), false)
+            .unwrap();
+        assert_eq!(json.lines().count(), 1);
+        assert!(json.starts_with(r#"{"port":1234,"#));
+    }
+
+    #[test]
+    fn it_refuses_to_write_deep_ini() {
+        let nested = serde_json::json!({"database": {"pool": {"size": 1}}});
+        assert!(matches!(
+            IniDeserializer::new().serialize(&nested, true),
+            Err(Error::Serialize(_))
+        ));
+    }
+
+    #[test]
+    fn it_keeps_the_database_url_out_of_debug_output() {
+        let config: Config = deserialize_config(&JsonDeserializer::new(), JSON).unwrap();
+        assert!(!format!("{config:?}").contains("postgresql://"));
+        assert!(format!("{:?}", config.revealed()).contains("postgresql://user@database"));
+    }
+
+    #[test]
+    fn it_resolves_secret_references() {
+        // SAFETY: no other test reads or writes this variable
+        unsafe { std::env::set_var("CONFIG_READER_TEST_DATABASE_URL", "postgresql://env@db/db") };
+        let yaml = YAML.replace(
+            "postgresql://user@database:5432/db",
+            "${env:CONFIG_READER_TEST_DATABASE_URL}",
+        );
+
+        let config: Config = deserialize_config(&YmlDeserializer::new(), &yaml).unwrap();
+        assert_eq!(config.database.url.expose(), "postgresql://env@db/db");
+
+        // Converting keeps the reference rather than writing the secret out
+        let converted = YmlDeserializer::new().serialize(&config, true).unwrap();
+        assert!(converted.contains("${env:CONFIG_READER_TEST_DATABASE_URL}"));
+        assert!(!converted.contains("postgresql://env@db/db"));
+    }
+}
@@ -0,0 +1,168 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::{
+    Config, ConfigDeserializer, OwnedConfig, deserialize_config,
+    error::{self, ConfigError, Error},
+    include, interpolate,
+    layered::LayeredLoader,
+    migrate, profile,
+    registry::FormatRegistry,
+    source::{self, ConfigSource, FormatHint},
+    strict,
+};
+
+/// A loaded config, with where its values came from if that was asked for
+pub struct Loaded {
+    pub config: OwnedConfig,
+    pub provenance: Option<BTreeMap<String, String>>,
+}
+
+/// How `load_config` should go about loading
+#[derive(Default, Clone)]
+pub struct LoadOptions {
+    /// Read every input as this format, instead of going by extension or contents
+    pub format_override: Option<Arc<dyn ConfigDeserializer>>,
+    /// Overrides from the environment, applied on top of every input
+    pub env: Option<serde_json::Value>,
+    pub with_provenance: bool,
+    /// Reject unknown keys, duplicate keys and quoted scalars, see `strict::audit`
+    pub strict: bool,
+    /// Which section of profiled configs to use on top of `default`
+    pub profile: Option<String>,
+}
+
+/// Load `inputs` (paths, `-` for stdin, `env:NAME` or `http://` URLs, see `source::from_input`)
+/// on top of each other, then the environment, and validate the result
+pub fn load_config(
+    registry: &FormatRegistry,
+    inputs: &[String],
+    options: LoadOptions,
+) -> Result<Loaded, ConfigError> {
+    let sources = inputs
+        .iter()
+        .map(|input| source::from_input(input).map_err(|e| ConfigError::from(e).with_path(input)))
+        .collect::<Result<Vec<_>, _>>()?;
+    load_sources(registry, &sources, options)
+}
+
+/// `load_config` for sources that aren't on the command line, eg. `source::Inline`
+pub fn load_sources(
+    registry: &FormatRegistry,
+    inputs: &[Box<dyn ConfigSource>],
+    options: LoadOptions,
+) -> Result<Loaded, ConfigError> {
+    let LoadOptions {
+        format_override,
+        env,
+        with_provenance,
+        strict,
+        profile,
+    } = options;
+    if inputs.is_empty() {
+        let msg = "Please specify the input path(s), or `-` to read from stdin";
+        return Err(Error::Usage(msg.to_string()).into());
+    }
+
+    let mut sources = Vec::new();
+    for input in inputs {
+        sources.extend(include::sources(
+            registry,
+            input.as_ref(),
+            format_override.clone(),
+        )?);
+    }
+
+    let fields = error::struct_fields::<Config>();
+
+    // A single plain file with nothing to merge onto it is deserialized directly, which keeps
+    // errors pointing at the right line of the file. Anything else goes through the loader, as do
+    // versions it doesn't know so that it can say so. Every known version deserializes as it is,
+    // thanks to serde aliases, so none of them need migrating first.
+    let plain = |source: &include::Source| {
+        source
+            .format
+            .deserialize::<serde_json::Value>(&source.contents)
+            .is_ok_and(|document| {
+                !profile::is_profiled(&document)
+                    && !interpolate::has_references(&document)
+                    && migrate::version(&document).is_ok()
+            })
+    };
+    if let ([source], None, false, None) = (sources.as_slice(), &env, with_provenance, &profile)
+        && plain(source)
+    {
+        let include::Source {
+            name,
+            contents,
+            format,
+        } = source;
+        if strict {
+            strict::check(format.as_ref(), contents)
+                .map_err(|e| ConfigError::from(e).with_path(name))?;
+        }
+        let config: Config = deserialize_config(format.as_ref(), contents)
+            .map_err(|e| ConfigError::from(e).with_source(name, contents, fields))?;
+        config
+            .validate()
+            .map_err(|e| ConfigError::from(e).with_path(name))?;
+        return Ok(Loaded {
+            config: config.into_owned(),
+            provenance: None,
+        });
+    }
+
+    let mut loader = LayeredLoader::new().strict(strict).profile(profile);
+    for include::Source {
+        name,
+        contents,
+        format,
+    } in &sources
+    {
+        loader = loader
+            .add_contents(name, format.as_ref(), contents)
+            .map_err(|e| ConfigError::from(e).with_source(name, contents, fields))?;
+    }
+    if let Some(env) = env {
+        loader = loader.add_layer("env", env);
+    }
+
+    let config = loader.config()?;
+    config.validate()?;
+    Ok(Loaded {
+        config,
+        provenance: with_provenance.then(|| loader.provenance().clone()),
+    })
+}
+
+/// Read a path (or stdin for `-`, see `source::from_input` for the rest) and figure out which
+/// format it is in
+pub fn read_input(
+    registry: &FormatRegistry,
+    input: &str,
+    format_override: Option<Arc<dyn ConfigDeserializer>>,
+) -> Result<(String, Arc<dyn ConfigDeserializer>), ConfigError> {
+    let source = source::from_input(input).map_err(|e| ConfigError::from(e).with_path(input))?;
+    read_source(registry, source.as_ref(), format_override)
+}
+
+/// Read `source` and figure out which format it is in
+pub fn read_source(
+    registry: &FormatRegistry,
+    source: &dyn ConfigSource,
+    format_override: Option<Arc<dyn ConfigDeserializer>>,
+) -> Result<(String, Arc<dyn ConfigDeserializer>), ConfigError> {
+    let name = source.name();
+    let document = source
+        .read()
+        .map_err(|e| ConfigError::from(e).with_path(name))?;
+
+    // An explicit `--format` wins, then what the source says, then whatever the contents look like
+    let format = format_override
+        .or_else(|| match document.format_hint? {
+            FormatHint::Extension(extension) => registry.by_extension(&extension),
+            FormatHint::MimeType(mime_type) => registry.by_mime_type(&mime_type),
+        })
+        .or_else(|| registry.sniff(&document.contents))
+        .ok_or_else(|| ConfigError::from(Error::UnknownFormat).with_path(name))?;
+    Ok((document.contents, format))
+}
@@ -1,111 +1,627 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, process::ExitCode, sync::Arc, time::Duration};
 
-use serde::{Deserialize, Serialize};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use config_reader::{
+    ConfigDeserializer, ConfigError, ConfigWatcher, Error, FormatRegistry, LoadOptions,
+    OwnedConfig, ReloadEvent, diff, edit, layered, lint, load_config, load_sources, migrate,
+    profile, query, read_input, read_source, schema,
+    source::{self, ConfigSource},
+    strict,
+};
 
-fn main() {
-    let mut args = std::env::args();
-    // Unwrapping is OK here, as UTF-8 Strings can always be converted to PathBufs
-    let Some(path) = args.nth(1).map(PathBuf::from) else {
-        eprintln!("Please specify the input path");
-        return;
-    };
-    // Unwrapping is Ok as `path` was created from UTF-8 string, and so is the extension
-    let extension = if let Some(ext) = path.extension().map(|o| o.to_str().unwrap()) {
-        Some(ext)
-    } else {
-        eprintln!("File has no extension!");
-        return;
-    };
-    let file_contents = match std::fs::read_to_string(&path) {
-        Ok(c) => c,
+const EXIT_CODES: &str = "\
+Exit codes:
+  0  Success
+  2  Bad usage, or a profile the config doesn't have
+  3  A file couldn't be read or written
+  4  The format couldn't be detected, or doesn't support what was asked
+  5  The config couldn't be parsed
+  6  The config parsed, but failed validation, strict mode or lint, or needs migrating
+  7  `get` was asked for a key the config doesn't have";
+
+/// Reads the imaginary config file, in any of JSON, YAML, TOML and INI
+#[derive(Parser)]
+#[command(
+    name = "learn_rs_config_reader",
+    after_help = EXIT_CODES,
+    args_conflicts_with_subcommands = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Same as `formats`, from before there were subcommands
+    #[arg(long)]
+    list_formats: bool,
+
+    /// Without a subcommand, we `parse`
+    #[command(flatten)]
+    parse: ParseArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Load the config, layered with `APP_*` environment variables, and print it
+    Parse(ParseArgs),
+    /// Check a config against the JSON Schema, listing every violation
+    Validate(ValidateArgs),
+    /// Write a config out in another format
+    Convert(ConvertArgs),
+    /// Print a single value of the config
+    Get(GetArgs),
+    /// Change a single value in a YAML or JSON file, keeping its comments and layout
+    Set(SetArgs),
+    /// Print the JSON Schema of the config
+    Schema,
+    /// Compare two configs field by field
+    Diff(DiffArgs),
+    /// List everything suspicious about a config
+    Lint(LintArgs),
+    /// Upgrade configs written for an older version, rewriting them in place
+    Migrate(MigrateArgs),
+    /// List the formats we can read and write
+    Formats,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Output {
+    /// Rust's `{:#?}`
+    Debug,
+    Json,
+    Yaml,
+}
+
+/// How to read the inputs, shared by every subcommand that reads a config
+#[derive(Args)]
+struct InputArgs {
+    /// Read the inputs as this format, instead of going by extension or contents
+    #[arg(long, value_name = "FORMAT")]
+    format: Option<String>,
+
+    /// Apply this section of profiled configs on top of `default`
+    #[arg(long, value_name = "NAME", env = profile::PROFILE_ENV)]
+    profile: Option<String>,
+}
+
+#[derive(Args)]
+struct ParseArgs {
+    /// Config files, layered in order, or `-` for stdin, `env:NAME` or an `http://` URL
+    #[arg(value_name = "INPUT")]
+    inputs: Vec<String>,
+
+    /// How to print the config
+    #[arg(long, value_enum, default_value_t = Output::Debug)]
+    output: Output,
+
+    /// Also print which file or variable each value came from
+    #[arg(long)]
+    provenance: bool,
+
+    /// Print secrets in plain text
+    #[arg(long)]
+    show_secrets: bool,
+
+    /// Reject unknown keys, duplicate keys and quoted scalars
+    #[arg(long)]
+    strict: bool,
+
+    /// Print the config again every time the file changes
+    #[arg(long, conflicts_with = "provenance")]
+    watch: bool,
+
+    #[command(flatten)]
+    input: InputArgs,
+}
+
+#[derive(Args)]
+struct ValidateArgs {
+    /// Config file, or `-` for stdin, `env:NAME` or an `http://` URL
+    #[arg(value_name = "INPUT")]
+    config: String,
+
+    /// JSON Schema to check against, instead of our own
+    #[arg(long, value_name = "PATH")]
+    schema: Option<String>,
+
+    /// Also list what strict mode would complain about
+    #[arg(long)]
+    strict: bool,
+
+    #[command(flatten)]
+    input: InputArgs,
+}
+
+#[derive(Args)]
+struct SetArgs {
+    /// Dotted path (`database.url`) or JSON pointer (`/database/url`) of the value
+    key: String,
+
+    /// The new value, read as a YAML value: `8080` is a number, `[a, b]` a list and `true` a
+    /// boolean
+    value: String,
+
+    /// Config file to edit in place
+    #[arg(value_name = "INPUT")]
+    path: PathBuf,
+
+    /// Keep the value a string, whatever it looks like
+    #[arg(long)]
+    string: bool,
+
+    #[command(flatten)]
+    input: InputArgs,
+}
+
+#[derive(Args)]
+struct MigrateArgs {
+    /// Config files to upgrade, each written back in the format it is in
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+
+    /// Only list what would change, and fail if anything would
+    #[arg(long)]
+    check: bool,
+
+    #[command(flatten)]
+    input: InputArgs,
+}
+
+#[derive(Args)]
+struct ConvertArgs {
+    /// Config files, layered in order, or `-` for stdin, `env:NAME` or an `http://` URL
+    #[arg(value_name = "INPUT", required = true)]
+    inputs: Vec<String>,
+
+    /// Format to write, by default the one matching the extension of `--out`
+    #[arg(long, value_name = "FORMAT")]
+    to: Option<String>,
+
+    /// Write to this file instead of stdout
+    #[arg(short, long, value_name = "PATH")]
+    out: Option<PathBuf>,
+
+    /// Leave out the whitespace
+    #[arg(long)]
+    compact: bool,
+
+    /// Reject unknown keys, duplicate keys and quoted scalars
+    #[arg(long)]
+    strict: bool,
+
+    #[command(flatten)]
+    input: InputArgs,
+}
+
+#[derive(Args)]
+struct GetArgs {
+    /// Dotted path of the value, eg. `database.url`, or a JSON pointer, eg. `/database/url`
+    key: String,
+
+    /// Config files, layered in order, or `-` for stdin, `env:NAME` or an `http://` URL
+    #[arg(value_name = "INPUT", required = true)]
+    inputs: Vec<String>,
+
+    /// How to print the value. By default strings are printed without quotes, and anything else
+    /// as JSON on a single line.
+    #[arg(long, value_enum)]
+    output: Option<Output>,
+
+    /// Print secrets in plain text
+    #[arg(long)]
+    show_secrets: bool,
+
+    #[command(flatten)]
+    input: InputArgs,
+}
+
+#[derive(Args)]
+struct DiffArgs {
+    /// The config to compare against
+    old: String,
+
+    /// The config to compare
+    new: String,
+
+    /// Print the changes as JSON
+    #[arg(long)]
+    json: bool,
+
+    #[command(flatten)]
+    input: InputArgs,
+}
+
+#[derive(Args)]
+struct LintArgs {
+    /// Config files, layered in order, or `-` for stdin, `env:NAME` or an `http://` URL
+    #[arg(value_name = "INPUT", required = true)]
+    inputs: Vec<String>,
+
+    /// Print the findings as JSON
+    #[arg(long)]
+    json: bool,
+
+    #[command(flatten)]
+    input: InputArgs,
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
         Err(e) => {
-            // `path` was created from an UTF-8 string, so can be converted to one
-            eprintln!(
-                "Error reading file at path {}: {}",
-                path.to_str().unwrap(),
-                e
-            );
-            return;
+            eprintln!("{e}");
+            ExitCode::from(e.exit_code())
         }
+    }
+}
+
+fn run(cli: Cli) -> Result<(), ConfigError> {
+    let registry = FormatRegistry::default();
+    let command = match cli.command {
+        Some(command) => command,
+        None if cli.list_formats => Command::Formats,
+        None => Command::Parse(cli.parse),
     };
+    match command {
+        Command::Parse(args) => parse(&registry, args),
+        Command::Validate(args) => validate(&registry, args),
+        Command::Convert(args) => convert(&registry, args),
+        Command::Get(args) => get(&registry, args),
+        Command::Set(args) => set(&registry, args),
+        Command::Schema => {
+            let schema = schema::config_schema();
+            println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+            Ok(())
+        }
+        Command::Diff(args) => diff_configs(&registry, args),
+        Command::Lint(args) => lint_config(&registry, args),
+        Command::Migrate(args) => migrate_configs(&registry, args),
+        Command::Formats => {
+            list_formats(&registry);
+            Ok(())
+        }
+    }
+}
 
-    let result = match extension {
-        Some("yml" | "yaml") => deserialize_config(&YmlDeserializer::new(), &file_contents),
-        Some("json") => deserialize_config(&JsonDeserializer::new(), &file_contents),
-        _ => {
-            eprintln!("Unsupported file extension : {extension:?}");
-            return;
+fn parse(registry: &FormatRegistry, args: ParseArgs) -> Result<(), ConfigError> {
+    let format_override = format_arg(registry, "--format", args.input.format.as_deref())?;
+    let env = layered::env_layer(layered::ENV_PREFIX, std::env::vars());
+    let options = LoadOptions {
+        format_override,
+        env,
+        with_provenance: args.provenance,
+        strict: args.strict,
+        profile: args.input.profile,
+    };
+    if args.watch {
+        let [input] = args.inputs.as_slice() else {
+            let msg = "--watch needs exactly one file to watch";
+            return Err(Error::Usage(msg.to_string()).into());
+        };
+        return watch_config(registry, input, options, args.output, args.show_secrets);
+    }
+
+    let loaded = load_config(registry, &args.inputs, options)?;
+
+    if let Output::Json | Output::Yaml = args.output {
+        let mut value = loaded.config.to_value(args.show_secrets)?;
+        if let Some(provenance) = loaded.provenance {
+            value = serde_json::json!({ "config": value, "provenance": provenance });
+        }
+        print!("{}", render(&value, args.output)?);
+        return Ok(());
+    }
+
+    if args.show_secrets {
+        println!("\nParsed config is:\n\n{:#?}\n", loaded.config.revealed());
+    } else {
+        println!("\nParsed config is:\n\n{:#?}\n", loaded.config);
+    }
+    if let Some(provenance) = loaded.provenance {
+        println!("Provenance:\n");
+        for (key, layer) in provenance {
+            println!("    {key} <- {layer}");
         }
+        println!();
+    }
+    Ok(())
+}
+
+/// Environment overrides are deliberately left out, converting a file should only ever depend
+/// on the file.
+fn convert(registry: &FormatRegistry, args: ConvertArgs) -> Result<(), ConfigError> {
+    // Without `--to`, go by the extension of the output file
+    let to = format_arg(registry, "--to", args.to.as_deref())?.or_else(|| {
+        let extension = args.out.as_ref()?.extension()?.to_str()?;
+        registry.by_extension(extension)
+    });
+    let Some(to) = to else {
+        let msg = "Please specify the format to convert to with `--to`";
+        return Err(Error::Usage(msg.to_string()).into());
     };
 
-    match result {
-        Ok(config) => println!("\nParsed config is:\n\n{config:#?}\n"),
-        Err(e) => eprintln!("Unable to parse config! : {e:?}"),
+    let options = LoadOptions {
+        format_override: format_arg(registry, "--format", args.input.format.as_deref())?,
+        strict: args.strict,
+        profile: args.input.profile,
+        ..Default::default()
+    };
+    let loaded = load_config(registry, &args.inputs, options)?;
+    let converted = to.serialize(&loaded.config, !args.compact)?;
+    match args.out {
+        Some(path) => std::fs::write(&path, converted)
+            .map_err(|e| ConfigError::from(Error::Io(e)).with_path(&path.display().to_string()))?,
+        None => print!("{converted}"),
     }
+    Ok(())
 }
 
-fn deserialize_config<'a>(
-    deserializer: &dyn ConfigDeserializer,
-    contents: &'a str,
-) -> Result<Config<'a>, Error> {
-    deserializer.deserialize(contents)
+/// Changes one value of the file in place, see `edit::set`
+fn set(registry: &FormatRegistry, args: SetArgs) -> Result<(), ConfigError> {
+    let input = source::File::new(&args.path);
+    let name = input.name();
+    let format_override = format_arg(registry, "--format", args.input.format.as_deref())?;
+    let (contents, format) = read_source(registry, &input, format_override)?;
+    let value = match args.string {
+        true => serde_json::Value::String(args.value),
+        false => serde_yaml::from_str(&args.value)
+            .unwrap_or_else(|_| serde_json::Value::String(args.value)),
+    };
+
+    let edited = edit::set(format.as_ref(), &contents, &args.key, &value)
+        .map_err(|e| ConfigError::from(e).with_source(name, &contents, &[]))?;
+
+    // Never write a config that doesn't load anymore. Files that didn't load as a whole config
+    // to begin with, like a layer with just a few overrides, are left to the layers they go with.
+    let load = |contents: &str| {
+        let source = source::Inline::new(name, contents).at(&args.path);
+        let options = LoadOptions {
+            format_override: Some(Arc::clone(&format)),
+            profile: args.input.profile.clone(),
+            ..LoadOptions::default()
+        };
+        load_sources(
+            registry,
+            &[Box::new(source) as Box<dyn ConfigSource>],
+            options,
+        )
+    };
+    if load(&contents).is_ok() {
+        load(&edited)?;
+    }
+    std::fs::write(&args.path, edited).map_err(|e| ConfigError::from(Error::Io(e)).with_path(name))
 }
 
-/// An imaginary config file
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Config<'a> {
-    port: u16,
-    base_url: &'a str,
-    s3_path: &'a str,
-    database_url: &'a str,
+/// Brings every input up to the current version, in the format it was written in. Comments and
+/// formatting don't survive, the document is written out fresh.
+fn migrate_configs(registry: &FormatRegistry, args: MigrateArgs) -> Result<(), ConfigError> {
+    let format_override = format_arg(registry, "--format", args.input.format.as_deref())?;
+    let mut outdated = Vec::new();
+    for path in &args.inputs {
+        let input = source::File::new(path);
+        let name = input.name();
+        let (contents, format) = read_source(registry, &input, format_override.clone())?;
+        let mut document: serde_json::Value = format
+            .deserialize(&contents)
+            .map_err(|e| ConfigError::from(e).with_source(name, &contents, &[]))?;
+        let from =
+            migrate::upgrade(&mut document).map_err(|e| ConfigError::from(e).with_path(name))?;
+        if from == migrate::CURRENT_VERSION {
+            println!("{name}: already at version {from}");
+            continue;
+        }
+        for migration in migrate::pending(from) {
+            println!("{name}: version {}: {}", migration.to, migration.summary);
+        }
+        if args.check {
+            outdated.push(name.to_string());
+            continue;
+        }
+
+        let migrated = format
+            .serialize(&document, true)
+            .map_err(|e| ConfigError::from(e).with_path(name))?;
+        std::fs::write(path, migrated)
+            .map_err(|e| ConfigError::from(Error::Io(e)).with_path(name))?;
+        let to = migrate::CURRENT_VERSION;
+        println!("{name}: migrated from version {from} to {to}");
+    }
+
+    if !outdated.is_empty() {
+        let msg = format!("{} need(s) migrating", outdated.join(", "));
+        return Err(Error::Invalid(msg).into());
+    }
+    Ok(())
 }
 
-#[derive(Debug)]
-/// Config deserialization error
-pub enum Error {
-    /// Something went wrong deserializing JSON
-    Json(serde_json::Error),
-    /// Something went wrong deserializing YAML
-    Yaml(serde_yaml::Error),
+/// Checks the document as written against a JSON Schema, and lists every violation
+fn validate(registry: &FormatRegistry, args: ValidateArgs) -> Result<(), ConfigError> {
+    let schema = match &args.schema {
+        Some(path) => {
+            let (contents, format) = read_input(registry, path, None)?;
+            format
+                .deserialize(&contents)
+                .map_err(|e| ConfigError::from(e).with_source(path, &contents, &[]))?
+        }
+        None => schema::config_schema(),
+    };
+
+    let input = args.config;
+    let format_override = format_arg(registry, "--format", args.input.format.as_deref())?;
+    let (contents, format) = read_input(registry, &input, format_override)?;
+    let mut document: serde_json::Value = format
+        .deserialize(&contents)
+        .map_err(|e| ConfigError::from(e).with_source(&input, &contents, &[]))?;
+    // Our own schema only describes the current layout, older ones are checked as if migrated
+    if args.schema.is_none() {
+        migrate::upgrade(&mut document).map_err(|e| ConfigError::from(e).with_path(&input))?;
+    }
+
+    let mut violations = schema::validate(&schema, &document).map_err(|e| match &args.schema {
+        Some(path) => ConfigError::from(e).with_path(path),
+        None => ConfigError::from(e),
+    })?;
+    if args.strict {
+        let audited = strict::audit(format.as_ref(), &contents, &schema)
+            .map_err(|e| ConfigError::from(e).with_source(&input, &contents, &[]))?;
+        violations.extend(audited);
+    }
+    if violations.is_empty() {
+        println!("{input} is valid");
+        return Ok(());
+    }
+    for violation in &violations {
+        eprintln!("{input}: {violation}");
+    }
+    let msg = format!("{} schema violation(s)", violations.len());
+    Err(ConfigError::from(Error::Invalid(msg)).with_path(&input))
 }
 
-// Had to rename this bc I didn't like the original name..
-// Original name = `DeserializeConfig`
-trait ConfigDeserializer {
-    /// Deserialize the contents into a `Config`
-    fn deserialize<'a>(&self, contents: &'a str) -> Result<Config<'a>, Error>;
+/// Prints the value the app would see, so environment overrides apply like they do for `parse`
+fn get(registry: &FormatRegistry, args: GetArgs) -> Result<(), ConfigError> {
+    let options = LoadOptions {
+        format_override: format_arg(registry, "--format", args.input.format.as_deref())?,
+        env: layered::env_layer(layered::ENV_PREFIX, std::env::vars()),
+        profile: args.input.profile,
+        ..Default::default()
+    };
+    let loaded = load_config(registry, &args.inputs, options)?;
+    let value = loaded.config.to_value(args.show_secrets)?;
+
+    let found = query::get(&value, &args.key)?;
+    match args.output {
+        Some(output) => print!("{}", render(found, output)?),
+        None => println!("{}", query::raw(found)),
+    }
+    Ok(())
 }
 
-struct JsonDeserializer {}
+/// Compares what the two configs deserialize to, so they don't need to be in the same format
+fn diff_configs(registry: &FormatRegistry, args: DiffArgs) -> Result<(), ConfigError> {
+    let format_override = format_arg(registry, "--format", args.input.format.as_deref())?;
+    let load = |input: &String| {
+        let options = LoadOptions {
+            format_override: format_override.clone(),
+            profile: args.input.profile.clone(),
+            ..Default::default()
+        };
+        load_config(registry, std::slice::from_ref(input), options)
+    };
+    let (old, new) = (load(&args.old)?, load(&args.new)?);
 
-impl JsonDeserializer {
-    fn new() -> Self {
-        Self {}
+    let changes = diff::config_diff(&old.config, &new.config)?;
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&changes).unwrap());
+    } else {
+        for change in &changes {
+            println!("{change}");
+        }
     }
+    Ok(())
 }
 
-impl ConfigDeserializer for JsonDeserializer {
-    fn deserialize<'a>(&self, contents: &'a str) -> Result<Config<'a>, Error> {
-        match serde_json::from_str(contents) {
-            Ok(result) => Ok(result),
-            Err(e) => Err(Error::Json(e)),
+/// Lists everything suspicious about the config, and fails if any of it is an error rather than a
+/// warning
+fn lint_config(registry: &FormatRegistry, args: LintArgs) -> Result<(), ConfigError> {
+    let options = LoadOptions {
+        format_override: format_arg(registry, "--format", args.input.format.as_deref())?,
+        profile: args.input.profile.clone(),
+        ..Default::default()
+    };
+    let loaded = load_config(registry, &args.inputs, options)?;
+    let findings = lint::lint(&loaded.config, args.input.profile.as_deref());
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&findings).unwrap());
+    } else {
+        for finding in &findings {
+            eprintln!("{finding}");
         }
     }
+
+    let errors = findings
+        .iter()
+        .filter(|f| f.severity == lint::Severity::Error)
+        .count();
+    if errors > 0 {
+        let msg = format!("{errors} lint error(s)");
+        return Err(Error::Invalid(msg).into());
+    }
+    Ok(())
 }
 
-struct YmlDeserializer {}
+/// The format a flag like `--format` names, looked up in `registry`
+fn format_arg(
+    registry: &FormatRegistry,
+    flag: &str,
+    name: Option<&str>,
+) -> Result<Option<Arc<dyn ConfigDeserializer>>, ConfigError> {
+    let Some(name) = name else {
+        return Ok(None);
+    };
+    match registry.by_name(name) {
+        Some(format) => Ok(Some(format)),
+        None => Err(Error::Usage(format!("{flag}: unsupported format `{name}`")).into()),
+    }
+}
 
-impl YmlDeserializer {
-    fn new() -> Self {
-        Self {}
+/// `value` the way `--output` asks for
+fn render(value: &serde_json::Value, output: Output) -> Result<String, Error> {
+    match output {
+        Output::Debug => Ok(format!("{value:#?}\n")),
+        Output::Json => serde_json::to_string_pretty(value)
+            .map(|json| json + "\n")
+            .map_err(|e| Error::Serialize(e.to_string())),
+        Output::Yaml => serde_yaml::to_string(value).map_err(|e| Error::Serialize(e.to_string())),
     }
 }
 
-impl ConfigDeserializer for YmlDeserializer {
-    fn deserialize<'a>(&self, contents: &'a str) -> Result<Config<'a>, Error> {
-        match serde_yaml::from_str(contents) {
-            Ok(result) => Ok(result),
-            Err(e) => Err(Error::Yaml(e)),
+/// Print the config, then print it again every time it changes until we're killed. Every
+/// reload is loaded with the same `options` as a one-off `parse`.
+fn watch_config(
+    registry: &FormatRegistry,
+    input: &str,
+    options: LoadOptions,
+    output: Output,
+    show_secrets: bool,
+) -> Result<(), ConfigError> {
+    let source = source::from_input(input).map_err(|e| ConfigError::from(e).with_path(input))?;
+    let Some(path) = source.path() else {
+        return Err(Error::Usage(format!("Can only watch files, not `{input}`")).into());
+    };
+    let watcher = ConfigWatcher::spawn(
+        path.to_path_buf(),
+        registry.clone(),
+        options,
+        Duration::from_millis(500),
+    )?;
+
+    let show = |config: &OwnedConfig| match output {
+        Output::Debug if show_secrets => Ok(format!("{:#?}", config.revealed())),
+        Output::Debug => Ok(format!("{config:#?}")),
+        output => render(&config.to_value(show_secrets)?, output),
+    };
+    // Subscribed before printing, so a reload in between is printed twice rather than never
+    let events = watcher.subscribe();
+    println!("\nParsed config is:\n\n{}\n", show(&watcher.current())?);
+    for event in events {
+        match event {
+            ReloadEvent::Updated(config) => {
+                println!("\nReloaded config is:\n\n{}\n", show(&config)?)
+            }
+            ReloadEvent::Rejected(e) => eprintln!("{e}\nKeeping the previous config"),
         }
     }
+    Ok(())
+}
+
+/// Print every format in `registry`, for `formats` and `--list-formats`
+fn list_formats(registry: &FormatRegistry) {
+    println!("{:<8} {:<16} MIME types", "Name", "Extensions");
+    for format in registry.formats() {
+        println!(
+            "{:<8} {:<16} {}",
+            format.name(),
+            format.extensions().join(", "),
+            format.mime_types().join(", ")
+        );
+    }
 }
@@ -0,0 +1,146 @@
+use serde_json::{Map, Value};
+
+use crate::{Error, interpolate::VARS_KEY, legacy, profile};
+
+/// Top level key saying which version of the layout a document was written for. Documents
+/// without one are taken to be version 1, from before there were versions.
+pub const VERSION_KEY: &str = "version";
+
+/// The layout `Config` has now
+pub const CURRENT_VERSION: u32 = 2;
+
+/// One step of the upgrade path, from the version before it to the next
+pub struct Migration {
+    /// The version this migration upgrades to
+    pub to: u32,
+    /// What changed, for `migrate` to tell the user
+    pub summary: &'static str,
+    /// Rewrite a single config (a whole document, or one of its profiles). Documents don't
+    /// always say which version they were written for, so this has to leave anything that is
+    /// already in the newer layout alone.
+    pub upgrade: fn(&mut Value),
+}
+
+/// Every migration in order, `MIGRATIONS[n]` upgrading version `n + 1`
+pub const MIGRATIONS: [Migration; 1] = [Migration {
+    to: 2,
+    summary: "`base_url`, `database_url` and `s3_path` became `base_urls`, `database.url` and \
+              `s3.bucket`",
+    upgrade: legacy::nest,
+}];
+
+/// The version `document` says it was written for
+pub fn version(document: &Value) -> Result<u32, Error> {
+    let Some(version) = document.get(VERSION_KEY) else {
+        return Ok(1);
+    };
+    let version = version
+        .as_u64()
+        .filter(|v| *v >= 1)
+        .ok_or_else(|| Error::Invalid(format!("`{VERSION_KEY}` must be a whole number from 1")))?;
+    if version > CURRENT_VERSION as u64 {
+        return Err(Error::Invalid(format!(
+            "version {version} is newer than this build knows about (up to \
+             {CURRENT_VERSION})"
+        )));
+    }
+    Ok(version as u32)
+}
+
+/// The migrations a config at version `from` goes through
+pub fn pending(from: u32) -> &'static [Migration] {
+    &MIGRATIONS[(from as usize - 1).min(MIGRATIONS.len())..]
+}
+
+/// Bring a single config (not a profiled document, see `upgrade`) from version `from` to
+/// `CURRENT_VERSION`
+pub fn apply(config: &mut Value, from: u32) {
+    for migration in pending(from) {
+        (migration.upgrade)(config);
+    }
+}
+
+/// Bring a whole document up to `CURRENT_VERSION`, every profile of it included, and mark it as
+/// such. Returns the version it was at.
+pub fn upgrade(document: &mut Value) -> Result<u32, Error> {
+    let from = version(document)?;
+    if profile::is_profiled(document) {
+        let sections = document
+            .as_object_mut()
+            .expect("profiled documents are maps");
+        for (name, section) in sections {
+            if name != VARS_KEY && section.is_object() {
+                apply(section, from);
+            }
+        }
+    } else {
+        apply(document, from);
+    }
+
+    if let Value::Object(map) = document
+        && from < CURRENT_VERSION
+    {
+        // Up front, where people look for it
+        let rest = std::mem::take(map);
+        *map = Map::from_iter([(VERSION_KEY.to_string(), Value::from(CURRENT_VERSION))]);
+        map.extend(rest.into_iter().filter(|(key, _)| key != VERSION_KEY));
+    }
+    Ok(from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn it_chains_migrations_to_the_current_version() {
+        assert_eq!(MIGRATIONS.len() as u32, CURRENT_VERSION - 1);
+        for (n, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.to, n as u32 + 2);
+        }
+        assert!(pending(CURRENT_VERSION).is_empty());
+    }
+
+    #[test]
+    fn it_upgrades_unversioned_documents() {
+        let mut document = json!({"port": 1234, "s3_path": "bucket"});
+        assert_eq!(upgrade(&mut document).unwrap(), 1);
+        assert_eq!(
+            serde_json::to_string(&document).unwrap(),
+            r#"{"version":2,"port":1234,"s3":{"bucket":"bucket"}}"#
+        );
+
+        // Already current, so nothing to do
+        let before = document.clone();
+        assert_eq!(upgrade(&mut document).unwrap(), CURRENT_VERSION);
+        assert_eq!(document, before);
+    }
+
+    #[test]
+    fn it_upgrades_every_profile() {
+        let mut document = json!({
+            "vars": {"s3_path": "not a config"},
+            "default": {"database_url": "postgresql://db/app"},
+            "prod": {"s3_path": "bucket"},
+        });
+        upgrade(&mut document).unwrap();
+        assert_eq!(
+            document["default"]["database"]["url"],
+            "postgresql://db/app"
+        );
+        assert_eq!(document["prod"]["s3"]["bucket"], "bucket");
+        assert_eq!(document["vars"]["s3_path"], "not a config");
+    }
+
+    #[test]
+    fn it_refuses_versions_it_does_not_know() {
+        let err = upgrade(&mut json!({"version": 99})).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid config: version 99 is newer than this build knows about (up to 2)"
+        );
+        assert!(version(&json!({"version": "two"})).is_err());
+        assert!(version(&json!({"version": 0})).is_err());
+    }
+}
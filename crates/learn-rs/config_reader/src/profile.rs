@@ -0,0 +1,112 @@
+use serde_json::Value;
+
+use crate::Error;
+
+/// Selects a profile when `--profile` isn't given
+pub const PROFILE_ENV: &str = "CONFIG_PROFILE";
+
+/// The section every profile is applied on top of
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Whether `document` is split up into profiles, ie. has a `default` section
+pub fn is_profiled(document: &Value) -> bool {
+    document.get(DEFAULT_PROFILE).is_some_and(Value::is_object)
+}
+
+/// The profiles a profiled document defines, not counting `default`
+pub fn available(document: &Value) -> Vec<String> {
+    let Value::Object(sections) = document else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = sections
+        .keys()
+        .filter(|name| *name != DEFAULT_PROFILE)
+        .cloned()
+        .collect();
+    names.sort();
+    names
+}
+
+/// Split a document into the layers it stands for, named by section. A profiled document becomes
+/// its `default` section followed by the `profile` section, if one was selected. Any other
+/// document is a single, unnamed layer.
+pub fn layers(document: Value, profile: Option<&str>) -> Result<Vec<(String, Value)>, Error> {
+    if !is_profiled(&document) {
+        return Ok(vec![(String::new(), document)]);
+    }
+    let available = available(&document);
+    let Value::Object(mut sections) = document else {
+        unreachable!("profiled documents are maps");
+    };
+
+    if let Some((key, _)) = sections.iter().find(|(_, section)| !section.is_object()) {
+        let msg = format!("`{key}` is outside of any profile, move it into `{DEFAULT_PROFILE}`");
+        return Err(Error::Invalid(msg));
+    }
+
+    let default = sections.remove(DEFAULT_PROFILE).expect("checked above");
+    let mut layers = vec![(DEFAULT_PROFILE.to_string(), default)];
+    if let Some(name) = profile.filter(|name| *name != DEFAULT_PROFILE) {
+        match sections.remove(name) {
+            Some(section) => layers.push((name.to_string(), section)),
+            None => {
+                return Err(Error::UnknownProfile {
+                    name: name.to_string(),
+                    available,
+                });
+            }
+        }
+    }
+    Ok(layers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn profiled() -> Value {
+        json!({
+            "default": {"port": 1234, "base_url": "https://example.com"},
+            "dev": {"base_url": "http://localhost"},
+            "prod": {"port": 443},
+        })
+    }
+
+    #[test]
+    fn it_passes_unprofiled_documents_through() {
+        let document = json!({"port": 1234});
+        let layers = layers(document.clone(), Some("prod")).unwrap();
+        assert_eq!(layers, [(String::new(), document)]);
+    }
+
+    #[test]
+    fn it_applies_the_selected_profile_on_top_of_default() {
+        let names = |profile| -> Vec<String> {
+            layers(profiled(), profile)
+                .unwrap()
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect()
+        };
+        assert_eq!(names(None), ["default"]);
+        assert_eq!(names(Some("default")), ["default"]);
+        assert_eq!(names(Some("prod")), ["default", "prod"]);
+    }
+
+    #[test]
+    fn it_lists_available_profiles_when_one_is_missing() {
+        let err = layers(profiled(), Some("staging")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown profile `staging`, available profiles: dev, prod"
+        );
+    }
+
+    #[test]
+    fn it_rejects_keys_outside_of_profiles() {
+        let mut document = profiled();
+        document["port"] = json!(80);
+        assert!(matches!(layers(document, None), Err(Error::Invalid(_))));
+    }
+}
@@ -0,0 +1,131 @@
+use serde_json::Value;
+
+use crate::{Error, error::is_similar};
+
+/// The value at `path` in `document`. `path` is either dotted, `database.url` (with list items
+/// indexed by number, `base_urls.0`), or a JSON pointer, `/database/url`.
+pub fn get<'v>(document: &'v Value, path: &str) -> Result<&'v Value, Error> {
+    let segments = segments(path);
+    let mut node = document;
+    for (depth, segment) in segments.iter().enumerate() {
+        let child = match node {
+            Value::Object(map) => map.get(segment),
+            Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => None,
+        };
+        let Some(child) = child else {
+            let suggestion = match node {
+                Value::Object(map) => map
+                    .keys()
+                    .find(|key| is_similar(key, segment))
+                    .map(|key| join(&segments[..depth], key, path)),
+                _ => None,
+            };
+            return Err(Error::MissingKey {
+                key: path.to_string(),
+                suggestion,
+            });
+        };
+        node = child;
+    }
+    Ok(node)
+}
+
+/// `value` the way a shell script wants it: strings without quotes, anything else as compact JSON
+pub fn raw(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// The keys along `path`, unescaping `~1` and `~0` in JSON pointers
+pub(crate) fn segments(path: &str) -> Vec<String> {
+    match path.strip_prefix('/') {
+        Some(pointer) => pointer
+            .split('/')
+            .map(|s| s.replace("~1", "/").replace("~0", "~"))
+            .collect(),
+        None if path.is_empty() => Vec::new(),
+        None => path.split('.').map(str::to_string).collect(),
+    }
+}
+
+/// `parents` followed by `key`, written the same way as `path` was
+fn join(parents: &[String], key: &str, path: &str) -> String {
+    let mut segments = parents.to_vec();
+    segments.push(key.to_string());
+    match path.starts_with('/') {
+        true => segments
+            .iter()
+            .map(|s| format!("/{}", s.replace('~', "~0").replace('/', "~1")))
+            .collect(),
+        false => segments.join("."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn document() -> Value {
+        json!({
+            "port": 1234,
+            "database": {"url": "postgresql://db/app", "pool_size": 8},
+            "base_urls": ["https://a.example", "https://b.example"],
+            "a/b": true,
+        })
+    }
+
+    #[test]
+    fn it_follows_dotted_paths_and_pointers() {
+        let document = document();
+        assert_eq!(get(&document, "port").unwrap(), 1234);
+        assert_eq!(get(&document, "database.pool_size").unwrap(), 8);
+        assert_eq!(
+            get(&document, "/database/url").unwrap(),
+            "postgresql://db/app"
+        );
+        assert_eq!(get(&document, "base_urls.1").unwrap(), "https://b.example");
+        assert_eq!(get(&document, "/a~1b").unwrap(), true);
+        assert_eq!(get(&document, "").unwrap(), &document);
+    }
+
+    #[test]
+    fn it_prints_raw_values() {
+        let document = document();
+        assert_eq!(
+            raw(get(&document, "database.url").unwrap()),
+            "postgresql://db/app"
+        );
+        assert_eq!(raw(get(&document, "port").unwrap()), "1234");
+        assert_eq!(
+            raw(get(&document, "base_urls").unwrap()),
+            r#"["https://a.example","https://b.example"]"#
+        );
+    }
+
+    #[test]
+    fn it_suggests_keys_that_are_close() {
+        let document = document();
+        let err = get(&document, "database.pool_sise").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`database.pool_sise` isn't set, did you mean `database.pool_size`?"
+        );
+        let err = get(&document, "/database/ulr").unwrap_err();
+        assert!(
+            err.to_string().ends_with("did you mean `/database/url`?"),
+            "{err}"
+        );
+        assert!(matches!(
+            get(&document, "port.number"),
+            Err(Error::MissingKey {
+                suggestion: None,
+                ..
+            })
+        ));
+        assert_eq!(get(&document, "base_urls.2").unwrap_err().exit_code(), 7);
+    }
+}
@@ -0,0 +1,204 @@
+use std::sync::Arc;
+
+use crate::{
+    ConfigDeserializer, IniDeserializer, JsonDeserializer, TomlDeserializer, YmlDeserializer,
+};
+
+/// The `ConfigDeserializer`s we know about, looked up by name, extension, MIME type or contents.
+///
+/// Nothing here is specific to the formats we ship with, so other crates can `register` their
+/// own at runtime.
+#[derive(Clone)]
+pub struct FormatRegistry {
+    formats: Vec<Arc<dyn ConfigDeserializer>>,
+}
+
+impl FormatRegistry {
+    /// A registry without any formats in it
+    pub fn empty() -> Self {
+        Self {
+            formats: Vec::new(),
+        }
+    }
+
+    /// Add a format. One registered under a name that is already taken replaces the old one, but
+    /// keeps its place in line for `sniff`.
+    pub fn register(&mut self, format: Arc<dyn ConfigDeserializer>) -> &mut Self {
+        match self.formats.iter_mut().find(|f| f.name() == format.name()) {
+            Some(existing) => *existing = format,
+            None => self.formats.push(format),
+        }
+        self
+    }
+
+    /// Every registered format, in registration order
+    pub fn formats(&self) -> impl Iterator<Item = &Arc<dyn ConfigDeserializer>> {
+        self.formats.iter()
+    }
+
+    /// Look a format up by what was given to `--format`, which may be its name, any of its
+    /// extensions or any of its MIME types
+    pub fn by_name(&self, name: &str) -> Option<Arc<dyn ConfigDeserializer>> {
+        let lowercase = name.to_ascii_lowercase();
+        self.find(|f| f.name() == lowercase)
+            .or_else(|| self.by_extension(name))
+            .or_else(|| self.by_mime_type(name))
+    }
+
+    pub fn by_extension(&self, extension: &str) -> Option<Arc<dyn ConfigDeserializer>> {
+        let extension = extension.to_ascii_lowercase();
+        self.find(|f| f.extensions().contains(&extension.as_str()))
+    }
+
+    pub fn by_mime_type(&self, mime_type: &str) -> Option<Arc<dyn ConfigDeserializer>> {
+        // Ignore parameters like `; charset=utf-8`
+        let mime_type = mime_type.split(';').next().unwrap_or_default().trim();
+        self.find(|f| {
+            f.mime_types()
+                .iter()
+                .any(|m| m.eq_ignore_ascii_case(mime_type))
+        })
+    }
+
+    /// Guess the format by looking at the contents of a config. Formats get asked in registration
+    /// order, the first one to recognise the contents wins.
+    pub fn sniff(&self, contents: &str) -> Option<Arc<dyn ConfigDeserializer>> {
+        self.find(|f| f.sniff(contents))
+    }
+
+    fn find(
+        &self,
+        predicate: impl Fn(&dyn ConfigDeserializer) -> bool,
+    ) -> Option<Arc<dyn ConfigDeserializer>> {
+        self.formats.iter().find(|f| predicate(f.as_ref())).cloned()
+    }
+}
+
+impl Default for FormatRegistry {
+    /// A registry with the formats we ship with
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry
+            .register(Arc::new(JsonDeserializer::new()))
+            .register(Arc::new(YmlDeserializer::new()))
+            .register(Arc::new(TomlDeserializer::new()))
+            .register(Arc::new(IniDeserializer::new()));
+        registry
+    }
+}
+
+/// The first line of a config that isn't blank or a comment
+pub fn first_line(contents: &str) -> Option<&str> {
+    contents
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with(';'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, Visit};
+
+    fn sniffed(contents: &str) -> Option<&'static str> {
+        FormatRegistry::default().sniff(contents).map(|f| f.name())
+    }
+
+    #[test]
+    fn it_sniffs_fixtures() {
+        assert_eq!(sniffed(include_str!("../config.json")), Some("json"));
+        assert_eq!(sniffed(include_str!("../config.yml")), Some("yaml"));
+        assert_eq!(sniffed(include_str!("../config.toml")), Some("toml"));
+        assert_eq!(sniffed(include_str!("../config.ini")), Some("ini"));
+    }
+
+    #[test]
+    fn it_sniffs_markers() {
+        assert_eq!(sniffed("---\nport: 1234\n"), Some("yaml"));
+        assert_eq!(sniffed("# comment\n\n  {\"port\": 1}"), Some("json"));
+        assert_eq!(sniffed("[server]\nport = 1234\n"), Some("toml"));
+        assert_eq!(sniffed("; comment\n[server]\nhost = a b\n"), Some("ini"));
+        assert_eq!(sniffed("url: http://x?a=b\n"), Some("yaml"));
+    }
+
+    #[test]
+    fn it_gives_up_on_garbage() {
+        assert_eq!(sniffed(""), None);
+        assert_eq!(sniffed("hello world"), None);
+    }
+
+    #[test]
+    fn it_looks_formats_up() {
+        let registry = FormatRegistry::default();
+        assert_eq!(registry.by_name("YAML").map(|f| f.name()), Some("yaml"));
+        assert_eq!(registry.by_name("yml").map(|f| f.name()), Some("yaml"));
+        assert_eq!(
+            registry.by_name("text/yaml").map(|f| f.name()),
+            Some("yaml")
+        );
+        assert_eq!(
+            registry
+                .by_name("Text/YAML; charset=utf-8")
+                .map(|f| f.name()),
+            Some("yaml")
+        );
+        assert_eq!(
+            registry.by_extension("json").map(|f| f.name()),
+            Some("json")
+        );
+        assert_eq!(
+            registry
+                .by_mime_type("application/json; charset=utf-8")
+                .map(|f| f.name()),
+            Some("json")
+        );
+        assert!(registry.by_name("xml").is_none());
+        assert!(registry.by_mime_type("application/xml").is_none());
+    }
+
+    /// JSON, but with a different name and extension, the way another crate might add a format
+    struct Json5Ish;
+
+    impl ConfigDeserializer for Json5Ish {
+        fn name(&self) -> &'static str {
+            "json5"
+        }
+
+        fn extensions(&self) -> &'static [&'static str] {
+            &["json5"]
+        }
+
+        fn mime_types(&self) -> &'static [&'static str] {
+            &["application/json5"]
+        }
+
+        fn deserialize_erased<'de>(
+            &self,
+            contents: &'de str,
+            visit: Visit<'de, '_>,
+        ) -> Result<(), Error> {
+            JsonDeserializer::new().deserialize_erased(contents, visit)
+        }
+    }
+
+    #[test]
+    fn it_registers_formats_at_runtime() {
+        let mut registry = FormatRegistry::default();
+        assert!(registry.by_extension("json5").is_none());
+
+        registry.register(Arc::new(Json5Ish));
+        let format = registry.by_extension("json5").unwrap();
+        assert_eq!(format.name(), "json5");
+        assert!(registry.by_mime_type("application/json5").is_some());
+
+        let port: serde_json::Value = format.deserialize(r#"{"port": 1}"#).unwrap();
+        assert_eq!(port["port"], 1);
+
+        // Doesn't sniff anything, so JSON still gets picked for JSON looking contents
+        assert_eq!(registry.sniff("{}").map(|f| f.name()), Some("json"));
+        assert_eq!(
+            registry.formats().map(|f| f.name()).collect::<Vec<_>>(),
+            ["json", "yaml", "toml", "ini", "json5"]
+        );
+    }
+}
@@ -0,0 +1,636 @@
+use std::{collections::HashMap, fmt};
+
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use crate::{
+    Error, OwnedConfig,
+    migrate::{CURRENT_VERSION, VERSION_KEY},
+};
+
+/// The JSON Schema describing `Config`
+pub fn config_schema() -> Value {
+    let schema = schemars::schema_for!(OwnedConfig);
+    let mut schema = serde_json::to_value(schema).expect("schemas are always valid JSON");
+    // Not a field of `Config`, but editors should know about it all the same
+    schema["properties"][VERSION_KEY] = serde_json::json!({
+        "description": "The version of the layout the config is written for, 1 if left out",
+        "type": "integer",
+        "minimum": 1,
+        "maximum": CURRENT_VERSION,
+    });
+    schema
+}
+
+/// One way in which a config doesn't match its schema
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    /// JSON Pointer to the offending value, `""` being the whole document
+    pub pointer: String,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pointer = if self.pointer.is_empty() {
+            "/"
+        } else {
+            &self.pointer
+        };
+        write!(f, "{pointer}: {}", self.message)
+    }
+}
+
+/// Keywords that only describe a value, and have nothing to check
+const ANNOTATIONS: &[&str] = &[
+    "$schema",
+    "$id",
+    "$comment",
+    "$defs",
+    "definitions",
+    "title",
+    "description",
+    "default",
+    "examples",
+    "deprecated",
+    "readOnly",
+    "writeOnly",
+];
+
+/// Keywords `validate` checks
+const ASSERTIONS: &[&str] = &[
+    "$ref",
+    "type",
+    "enum",
+    "const",
+    "minimum",
+    "maximum",
+    "exclusiveMinimum",
+    "exclusiveMaximum",
+    "minLength",
+    "maxLength",
+    "pattern",
+    "format",
+    "properties",
+    "patternProperties",
+    "additionalProperties",
+    "required",
+    "items",
+    "minItems",
+    "maxItems",
+    "uniqueItems",
+    "allOf",
+    "anyOf",
+    "oneOf",
+    "not",
+    "if",
+    "then",
+    "else",
+];
+
+/// Check `instance` against `schema`, collecting every violation rather than stopping at the first.
+///
+/// Only the keywords in `ASSERTIONS` are checked, which covers everything our generated schemas
+/// use and local `$ref`s. A schema using any other keyword is refused rather than half checked.
+pub fn validate(schema: &Value, instance: &Value) -> Result<Vec<Violation>, Error> {
+    let mut patterns = HashMap::new();
+    compile(schema, "", &mut patterns)?;
+    let mut validator = Validator {
+        root: schema,
+        patterns: &patterns,
+        violations: Vec::new(),
+    };
+    validator.check(schema, instance, "");
+    Ok(validator.violations)
+}
+
+/// Make sure `schema` only uses keywords `validate` knows, and compile the regexes in it.
+/// `pointer` is where `schema` is in the whole schema, for error messages.
+fn compile<'s>(
+    schema: &'s Value,
+    pointer: &str,
+    patterns: &mut HashMap<&'s str, Regex>,
+) -> Result<(), Error> {
+    let Value::Object(schema) = schema else {
+        return Ok(());
+    };
+    let mut regex = |pattern: &'s str, pointer: &str| match Regex::new(pattern) {
+        Ok(regex) => {
+            patterns.insert(pattern, regex);
+            Ok(())
+        }
+        Err(e) => Err(Error::Invalid(format!(
+            "`{pattern}` at `{pointer}` in the schema isn't a valid regex: {e}"
+        ))),
+    };
+
+    let mut subschemas = Vec::new();
+    for (keyword, value) in schema {
+        let at = format!("{pointer}/{}", escape(keyword));
+        match (keyword.as_str(), value) {
+            ("pattern", Value::String(pattern)) => regex(pattern, &at)?,
+            ("patternProperties", Value::Object(properties)) => {
+                for (pattern, subschema) in properties {
+                    let at = format!("{at}/{}", escape(pattern));
+                    regex(pattern, &at)?;
+                    subschemas.push((subschema, at));
+                }
+            }
+            ("properties" | "$defs" | "definitions", Value::Object(properties)) => {
+                subschemas.extend(
+                    properties
+                        .iter()
+                        .map(|(key, subschema)| (subschema, format!("{at}/{}", escape(key)))),
+                );
+            }
+            ("allOf" | "anyOf" | "oneOf", Value::Array(all)) => {
+                subschemas.extend(
+                    all.iter()
+                        .enumerate()
+                        .map(|(i, subschema)| (subschema, format!("{at}/{i}"))),
+                );
+            }
+            ("additionalProperties" | "items" | "not" | "if" | "then" | "else", subschema) => {
+                subschemas.push((subschema, at));
+            }
+            (keyword, _) if ASSERTIONS.contains(&keyword) || ANNOTATIONS.contains(&keyword) => {}
+            (keyword, _) => {
+                let msg = format!(
+                    "the schema uses `{keyword}` (at `{at}`), which `validate` can't check"
+                );
+                return Err(Error::Unsupported(msg));
+            }
+        }
+    }
+    for (subschema, at) in subschemas {
+        compile(subschema, &at, patterns)?;
+    }
+    Ok(())
+}
+
+struct Validator<'s> {
+    root: &'s Value,
+    /// Every regex in the schema, compiled by `compile`
+    patterns: &'s HashMap<&'s str, Regex>,
+    violations: Vec<Violation>,
+}
+
+impl<'s> Validator<'s> {
+    fn violation(&mut self, pointer: &str, message: impl Into<String>) {
+        self.violations.push(Violation {
+            pointer: pointer.to_string(),
+            message: message.into(),
+        });
+    }
+
+    fn check(&mut self, schema: &'s Value, instance: &Value, pointer: &str) {
+        let schema = match schema {
+            Value::Bool(true) => return,
+            Value::Bool(false) => return self.violation(pointer, "no value is allowed here"),
+            Value::Object(schema) => schema,
+            _ => return,
+        };
+
+        if let Some(Value::String(reference)) = schema.get("$ref") {
+            match self.resolve(reference) {
+                Some(target) => self.check(target, instance, pointer),
+                None => self.violation(pointer, format!("unresolvable $ref `{reference}`")),
+            }
+        }
+
+        if let Some(expected) = schema.get("type") {
+            let types: Vec<&str> = match expected {
+                Value::String(t) => vec![t.as_str()],
+                Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+                _ => Vec::new(),
+            };
+            if !types.is_empty() && !types.iter().any(|t| has_type(instance, t)) {
+                let message = format!(
+                    "expected {}, found {}",
+                    types.join(" or "),
+                    type_name(instance)
+                );
+                // Nothing else is going to make sense for a value of the wrong type
+                return self.violation(pointer, message);
+            }
+        }
+
+        if let Some(Value::Array(allowed)) = schema.get("enum")
+            && !allowed.contains(instance)
+        {
+            self.violation(
+                pointer,
+                format!("{instance} is not one of {}", Value::from(allowed.clone())),
+            );
+        }
+        if let Some(expected) = schema.get("const")
+            && expected != instance
+        {
+            self.violation(pointer, format!("expected {expected}, found {instance}"));
+        }
+
+        self.check_number(schema, instance, pointer);
+        self.check_string(schema, instance, pointer);
+        self.check_object(schema, instance, pointer);
+        self.check_array(schema, instance, pointer);
+        self.check_combinators(schema, instance, pointer);
+    }
+
+    fn check_number(&mut self, schema: &Map<String, Value>, instance: &Value, pointer: &str) {
+        let Some(n) = instance.as_f64() else {
+            return;
+        };
+        let bound = |key| schema.get(key).and_then(Value::as_f64);
+        if let Some(min) = bound("minimum").filter(|min| n < *min) {
+            self.violation(pointer, format!("{n} is less than the minimum of {min}"));
+        }
+        if let Some(max) = bound("maximum").filter(|max| n > *max) {
+            self.violation(pointer, format!("{n} is more than the maximum of {max}"));
+        }
+        if let Some(min) = bound("exclusiveMinimum").filter(|min| n <= *min) {
+            self.violation(pointer, format!("{n} must be more than {min}"));
+        }
+        if let Some(max) = bound("exclusiveMaximum").filter(|max| n >= *max) {
+            self.violation(pointer, format!("{n} must be less than {max}"));
+        }
+    }
+
+    fn check_string(&mut self, schema: &Map<String, Value>, instance: &Value, pointer: &str) {
+        let Value::String(s) = instance else {
+            return;
+        };
+        let len = s.chars().count() as u64;
+        let bound = |key| schema.get(key).and_then(Value::as_u64);
+        if let Some(min) = bound("minLength").filter(|min| len < *min) {
+            self.violation(pointer, format!("must be at least {min} characters long"));
+        }
+        if let Some(max) = bound("maxLength").filter(|max| len > *max) {
+            self.violation(pointer, format!("must be at most {max} characters long"));
+        }
+        if let Some(Value::String(pattern)) = schema.get("pattern")
+            && !self.matches(pattern, s)
+        {
+            self.violation(pointer, format!("`{s}` doesn't match `{pattern}`"));
+        }
+        if let Some(Value::String(format)) = schema.get("format")
+            && !has_format(s, format)
+        {
+            self.violation(pointer, format!("`{s}` is not a valid {format}"));
+        }
+    }
+
+    fn check_array(&mut self, schema: &'s Map<String, Value>, instance: &Value, pointer: &str) {
+        let Value::Array(values) = instance else {
+            return;
+        };
+        let len = values.len() as u64;
+        let bound = |key| schema.get(key).and_then(Value::as_u64);
+        if let Some(min) = bound("minItems").filter(|min| len < *min) {
+            self.violation(pointer, format!("must have at least {min} item(s)"));
+        }
+        if let Some(max) = bound("maxItems").filter(|max| len > *max) {
+            self.violation(pointer, format!("must have at most {max} item(s)"));
+        }
+        if schema.get("uniqueItems") == Some(&Value::Bool(true)) {
+            let repeated = (0..values.len())
+                .flat_map(|j| (0..j).map(move |i| (i, j)))
+                .find(|(i, j)| values[*i] == values[*j]);
+            if let Some((i, j)) = repeated {
+                self.violation(pointer, format!("items {i} and {j} are the same"));
+            }
+        }
+        if let Some(items) = schema.get("items") {
+            for (i, value) in values.iter().enumerate() {
+                self.check(items, value, &format!("{pointer}/{i}"));
+            }
+        }
+    }
+
+    fn check_object(&mut self, schema: &'s Map<String, Value>, instance: &Value, pointer: &str) {
+        let Value::Object(object) = instance else {
+            return;
+        };
+
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for key in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(key) {
+                    self.violation(pointer, format!("missing required key `{key}`"));
+                }
+            }
+        }
+
+        let properties = schema.get("properties").and_then(Value::as_object);
+        let pattern_properties = schema.get("patternProperties").and_then(Value::as_object);
+        for (key, value) in object {
+            let child = format!("{pointer}/{}", escape(key));
+            let mut matched = false;
+            for (pattern, property) in pattern_properties.into_iter().flatten() {
+                if self.matches(pattern, key) {
+                    matched = true;
+                    self.check(property, value, &child);
+                }
+            }
+            match (
+                properties.and_then(|p| p.get(key)),
+                schema.get("additionalProperties"),
+            ) {
+                (Some(property), _) => self.check(property, value, &child),
+                // `additionalProperties` is only for keys nothing else covers
+                (None, _) if matched => {}
+                (None, Some(Value::Bool(false))) => {
+                    self.violation(pointer, format!("unknown key `{key}`"));
+                }
+                (None, Some(additional)) => self.check(additional, value, &child),
+                (None, None) => {}
+            }
+        }
+    }
+
+    fn check_combinators(
+        &mut self,
+        schema: &'s Map<String, Value>,
+        instance: &Value,
+        pointer: &str,
+    ) {
+        if let Some(Value::Array(all)) = schema.get("allOf") {
+            for sub in all {
+                self.check(sub, instance, pointer);
+            }
+        }
+
+        if let Some(Value::Array(any)) = schema.get("anyOf")
+            && self.matching(any, instance, pointer) == 0
+        {
+            self.violation(pointer, "doesn't match any of the allowed schemas");
+        }
+        if let Some(Value::Array(one)) = schema.get("oneOf") {
+            let count = self.matching(one, instance, pointer);
+            if count != 1 {
+                self.violation(
+                    pointer,
+                    format!("must match exactly one schema, matches {count}"),
+                );
+            }
+        }
+        if let Some(not) = schema.get("not")
+            && self.passes(not, instance, pointer)
+        {
+            self.violation(pointer, "matches a schema it must not");
+        }
+
+        if let Some(condition) = schema.get("if") {
+            let branch = match self.passes(condition, instance, pointer) {
+                true => schema.get("then"),
+                false => schema.get("else"),
+            };
+            if let Some(branch) = branch {
+                self.check(branch, instance, pointer);
+            }
+        }
+    }
+
+    /// How many of `subs` `instance` matches
+    fn matching(&self, subs: &'s [Value], instance: &Value, pointer: &str) -> usize {
+        subs.iter()
+            .filter(|sub| self.passes(sub, instance, pointer))
+            .count()
+    }
+
+    /// Whether `instance` matches `schema`, without recording any violations
+    fn passes(&self, schema: &'s Value, instance: &Value, pointer: &str) -> bool {
+        let mut validator = Validator {
+            root: self.root,
+            patterns: self.patterns,
+            violations: Vec::new(),
+        };
+        validator.check(schema, instance, pointer);
+        validator.violations.is_empty()
+    }
+
+    fn matches(&self, pattern: &str, s: &str) -> bool {
+        // `compile` only finds the patterns of schemas it can tell are schemas, which a `$ref`
+        // could point past
+        match self.patterns.get(pattern) {
+            Some(regex) => regex.is_match(s),
+            None => Regex::new(pattern).is_ok_and(|regex| regex.is_match(s)),
+        }
+    }
+
+    /// Local references only, eg. `#/$defs/Database`
+    fn resolve(&self, reference: &str) -> Option<&'s Value> {
+        let pointer = reference.strip_prefix('#')?;
+        self.root.pointer(pointer)
+    }
+}
+
+fn has_type(instance: &Value, expected: &str) -> bool {
+    match expected {
+        "null" => instance.is_null(),
+        "boolean" => instance.is_boolean(),
+        "object" => instance.is_object(),
+        "array" => instance.is_array(),
+        "string" => instance.is_string(),
+        "number" => instance.is_number(),
+        "integer" => instance.is_i64() || instance.is_u64(),
+        _ => true,
+    }
+}
+
+fn type_name(instance: &Value) -> &'static str {
+    match instance {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Formats we don't know about are accepted, as the spec suggests
+fn has_format(s: &str, format: &str) -> bool {
+    match format {
+        "uri" => s.split_once(':').is_some_and(|(scheme, rest)| {
+            let mut chars = scheme.chars();
+            chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+                && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+                && !rest.is_empty()
+                && !s.contains(char::is_whitespace)
+        }),
+        "secret-reference" => {
+            (s.starts_with("${file:") || s.starts_with("${env:")) && s.ends_with('}')
+        }
+        _ => true,
+    }
+}
+
+/// Escape a key for use in a JSON Pointer
+pub(crate) fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy;
+    use serde_json::json;
+
+    fn violations(instance: Value) -> Vec<String> {
+        validate(&config_schema(), &instance)
+            .unwrap()
+            .iter()
+            .map(Violation::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn it_describes_config() {
+        let schema = config_schema();
+        assert_eq!(schema["type"], "object");
+        assert_eq!(schema["properties"]["base_urls"]["items"]["format"], "uri");
+        assert_eq!(schema["properties"]["port"]["maximum"], 65535);
+        let required = schema["required"].as_array().unwrap();
+        assert_eq!(required.len(), 4);
+    }
+
+    /// The fixtures are in the flat layout, the schema only describes the nested one
+    fn fixture(contents: &str) -> Value {
+        let mut document: Value = serde_yaml::from_str(contents).unwrap();
+        legacy::nest(&mut document);
+        document
+    }
+
+    #[test]
+    fn it_accepts_the_fixtures() {
+        let json = fixture(include_str!("../config.json"));
+        let yaml = fixture(include_str!("../config.yml"));
+        assert_eq!(violations(json), Vec::<String>::new());
+        assert_eq!(violations(yaml), Vec::<String>::new());
+    }
+
+    #[test]
+    fn it_accepts_secret_references() {
+        let mut config = fixture(include_str!("../config.json"));
+        config["database"]["url"] = json!("${file:/run/secrets/db}");
+        assert_eq!(violations(config), Vec::<String>::new());
+    }
+
+    #[test]
+    fn it_accepts_durations_and_sizes() {
+        let mut config = fixture(include_str!("../config.json"));
+        config["database"]["timeout"] = json!("1m");
+        config["limits"] = json!({"request_timeout": 5, "max_body_size": "1MiB"});
+        assert_eq!(violations(config), Vec::<String>::new());
+    }
+
+    #[test]
+    fn it_lists_every_violation() {
+        let found = violations(json!({
+            "port": 70000,
+            "base_urls": ["not a url"],
+            "s3": {"bucket": 5},
+            "features": {"beta": "yes"},
+        }));
+        assert_eq!(
+            found,
+            [
+                "/: missing required key `database`",
+                "/port: 70000 is more than the maximum of 65535",
+                "/base_urls/0: `not a url` is not a valid uri",
+                "/s3/bucket: expected string, found integer",
+                "/features/beta: expected boolean, found string",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_follows_refs_and_combinators() {
+        let schema = json!({
+            "$defs": {"Port": {"type": "integer", "minimum": 1}},
+            "type": "object",
+            "properties": {
+                "port": {"$ref": "#/$defs/Port"},
+                "mode": {"oneOf": [{"const": "a"}, {"const": "b"}]},
+                "tags": {"type": "array", "items": {"type": "string"}},
+            },
+            "additionalProperties": false,
+        });
+        let instance = json!({"port": 0, "mode": "c", "tags": ["x", 1], "extra": true});
+        let found: Vec<String> = validate(&schema, &instance)
+            .unwrap()
+            .iter()
+            .map(Violation::to_string)
+            .collect();
+        assert_eq!(
+            found,
+            [
+                "/port: 0 is less than the minimum of 1",
+                "/mode: must match exactly one schema, matches 0",
+                "/tags/1: expected string, found integer",
+                "/: unknown key `extra`",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_checks_list_lengths() {
+        let mut config = fixture(include_str!("../config.json"));
+        config["base_urls"] = json!([]);
+        assert_eq!(
+            violations(config),
+            ["/base_urls: must have at least 1 item(s)"]
+        );
+    }
+
+    #[test]
+    fn it_checks_patterns_and_conditions() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "name": {"type": "string", "pattern": "^[a-z]+$"},
+                "tags": {"type": "array", "maxItems": 2, "uniqueItems": true},
+                "cert": {"type": "string"},
+            },
+            "patternProperties": {"^x-": {"type": "string"}},
+            "additionalProperties": false,
+            "if": {"required": ["tls"]},
+            "then": {"required": ["cert"]},
+            "else": {"not": {"required": ["cert"]}},
+        });
+        let instance = json!({
+            "name": "App",
+            "tags": ["a", "b", "a"],
+            "x-team": 1,
+            "cert": "c",
+        });
+        let found: Vec<String> = validate(&schema, &instance)
+            .unwrap()
+            .iter()
+            .map(Violation::to_string)
+            .collect();
+        assert_eq!(
+            found,
+            [
+                "/name: `App` doesn't match `^[a-z]+$`",
+                "/tags: must have at most 2 item(s)",
+                "/tags: items 0 and 2 are the same",
+                "/x-team: expected string, found integer",
+                "/: matches a schema it must not",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_refuses_keywords_it_cannot_check() {
+        let schema = json!({"properties": {"a": {"dependentRequired": {"a": ["b"]}}}});
+        let err = validate(&schema, &json!({})).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the schema uses `dependentRequired` (at `/properties/a/dependentRequired`), which \
+             `validate` can't check"
+        );
+        let err = validate(&json!({"pattern": "("}), &json!("")).unwrap_err();
+        assert!(err.to_string().contains("isn't a valid regex"), "{err}");
+    }
+}
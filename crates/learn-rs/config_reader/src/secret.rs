@@ -0,0 +1,205 @@
+use std::{borrow::Cow, fmt};
+
+use schemars::{JsonSchema, SchemaGenerator, json_schema};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::DeserializeOwned};
+
+const REDACTED: &str = "[REDACTED]";
+
+/// A config value that must not end up in logs. `Debug` and `Display` redact it, `expose` is
+/// the only way to get at it.
+///
+/// Besides plain values, secrets may be references that get resolved while loading:
+///
+/// - `${file:/run/secrets/db}` reads the file (trailing newlines are trimmed)
+/// - `${env:DATABASE_URL}` reads the environment variable
+///
+/// A resolved secret remembers its reference and serializes back to it, so converting a config
+/// doesn't write the secret out in plain text.
+#[derive(Clone)]
+pub struct Secret<T> {
+    value: T,
+    reference: Option<String>,
+}
+
+impl<T> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Self {
+            value,
+            reference: None,
+        }
+    }
+}
+
+impl<T> Secret<T> {
+    pub fn expose(&self) -> &T {
+        &self.value
+    }
+
+    /// Swap the value out, keeping track of where it came from
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Secret<U> {
+        Secret {
+            value: f(self.value),
+            reference: self.reference,
+        }
+    }
+}
+
+impl<T> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.reference {
+            Some(reference) => write!(f, "{REDACTED} ({reference})"),
+            None => f.write_str(REDACTED),
+        }
+    }
+}
+
+impl<T> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T: PartialEq> PartialEq for Secret<T> {
+    /// Two secrets are the same if they hold the same value, no matter where it came from
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<T: Serialize> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match &self.reference {
+            Some(reference) => serializer.serialize_str(reference),
+            None => self.value.serialize(serializer),
+        }
+    }
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Secret<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Buffer the value, it's only a reference if it turns out to be a string
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let reference = value.as_str().and_then(parse_reference);
+
+        let (value, reference) = match reference {
+            Some(reference) => {
+                let resolved = reference.resolve().map_err(serde::de::Error::custom)?;
+                let original = value.as_str().map(str::to_string);
+                (serde_json::Value::String(resolved), original)
+            }
+            None => (value, None),
+        };
+
+        let value = T::deserialize(value).map_err(serde::de::Error::custom)?;
+        Ok(Self { value, reference })
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Secret<T> {
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn schema_name() -> Cow<'static, str> {
+        format!("Secret_{}", T::schema_name()).into()
+    }
+
+    /// Either the value itself, or a reference to where it can be read from
+    fn json_schema(generator: &mut SchemaGenerator) -> schemars::Schema {
+        json_schema!({
+            "anyOf": [
+                generator.subschema_for::<T>(),
+                {
+                    "type": "string",
+                    "format": "secret-reference",
+                    "pattern": "^\\$\\{(file|env):.+\\}$"
+                }
+            ],
+            "writeOnly": true
+        })
+    }
+}
+
+/// Where a secret should be read from
+#[derive(Debug, PartialEq)]
+enum Reference<'a> {
+    File(&'a str),
+    Env(&'a str),
+}
+
+impl Reference<'_> {
+    fn resolve(&self) -> Result<String, String> {
+        match self {
+            Self::File(path) => std::fs::read_to_string(path)
+                .map(|s| s.trim_end_matches(['\n', '\r']).to_string())
+                .map_err(|e| format!("unable to read secret from `{path}`: {e}")),
+            Self::Env(var) => {
+                std::env::var(var).map_err(|e| format!("unable to read secret from `${var}`: {e}"))
+            }
+        }
+    }
+}
+
+fn parse_reference(raw: &str) -> Option<Reference<'_>> {
+    let inner = raw.trim().strip_prefix("${")?.strip_suffix('}')?;
+    let (kind, target) = inner.split_once(':')?;
+    match kind {
+        "file" => Some(Reference::File(target)),
+        "env" => Some(Reference::Env(target)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_redacts() {
+        let secret = Secret::from("postgresql://user@database:5432/db".to_string());
+        assert_eq!(format!("{secret:?}"), REDACTED);
+        assert_eq!(format!("{secret}"), REDACTED);
+        assert_eq!(secret.expose(), "postgresql://user@database:5432/db");
+    }
+
+    #[test]
+    fn it_parses_references() {
+        assert_eq!(parse_reference("${env:DB}"), Some(Reference::Env("DB")));
+        assert_eq!(
+            parse_reference("${file:/run/secrets/db}"),
+            Some(Reference::File("/run/secrets/db"))
+        );
+        assert_eq!(parse_reference("${vault:db}"), None);
+        assert_eq!(parse_reference("postgresql://user@database"), None);
+    }
+
+    #[test]
+    fn it_resolves_file_references() {
+        let path =
+            std::env::temp_dir().join(format!("config_reader_{}_secret", std::process::id()));
+        std::fs::write(&path, "hunter2\n").unwrap();
+
+        let json = format!(r#""${{file:{}}}""#, path.display());
+        let secret: Secret<String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(secret.expose(), "hunter2");
+        // Writing it back out gives the reference, not the secret
+        assert_eq!(serde_json::to_string(&secret).unwrap(), json);
+        assert!(!format!("{secret:?}").contains("hunter2"));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn it_fails_on_unresolvable_references() {
+        let json = r#""${env:CONFIG_READER_SURELY_NOT_SET}""#;
+        let error = serde_json::from_str::<Secret<String>>(json).unwrap_err();
+        assert!(error.to_string().contains("CONFIG_READER_SURELY_NOT_SET"));
+    }
+
+    #[test]
+    fn it_deserializes_non_strings() {
+        let secret: Secret<u32> = serde_json::from_str("1234").unwrap();
+        assert_eq!(*secret.expose(), 1234);
+    }
+}
@@ -0,0 +1,423 @@
+use std::{
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::error::Error;
+
+/// How long `Http` waits for the server before giving up
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Somewhere a config document can be read from. The document is handed to whichever
+/// `ConfigDeserializer` the hint (or else the contents) points at, so sources don't know about
+/// formats at all.
+pub trait ConfigSource {
+    /// What errors and provenance call the source, eg. its path
+    fn name(&self) -> &str;
+
+    /// Read the whole document
+    fn read(&self) -> Result<Document, Error>;
+
+    /// The file behind the source, if there is one. Includes are resolved relative to it.
+    fn path(&self) -> Option<&Path> {
+        None
+    }
+}
+
+/// A document as read from a `ConfigSource`
+pub struct Document {
+    pub contents: String,
+    /// What the source knows about the format, if anything
+    pub format_hint: Option<FormatHint>,
+}
+
+pub enum FormatHint {
+    /// A file extension, without the dot
+    Extension(String),
+    /// A MIME type, eg. from a `Content-Type` header
+    MimeType(String),
+}
+
+/// The source the command line means by `input`: `-` for stdin, `env:NAME` for the document in
+/// an environment variable, an `http://` URL, or else a path
+pub fn from_input(input: &str) -> Result<Box<dyn ConfigSource>, Error> {
+    if input == "-" {
+        Ok(Box::new(Stdin))
+    } else if let Some(var) = input.strip_prefix("env:") {
+        Ok(Box::new(EnvVar::new(var)))
+    } else if is_url(input) {
+        Ok(Box::new(Http::new(input)?))
+    } else {
+        Ok(Box::new(File::new(input)))
+    }
+}
+
+/// Whether `input` looks like a URL rather than a path
+pub fn is_url(input: &str) -> bool {
+    input.starts_with("http://") || input.starts_with("https://")
+}
+
+/// A file on disk
+pub struct File {
+    path: PathBuf,
+    name: String,
+}
+
+impl File {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let name = path.display().to_string();
+        Self { path, name }
+    }
+}
+
+impl ConfigSource for File {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn read(&self) -> Result<Document, Error> {
+        Ok(Document {
+            contents: std::fs::read_to_string(&self.path).map_err(Error::Io)?,
+            format_hint: self
+                .path
+                .extension()
+                .map(|ext| FormatHint::Extension(ext.to_string_lossy().into_owned())),
+        })
+    }
+
+    fn path(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+}
+
+/// Standard input, read to the end
+pub struct Stdin;
+
+impl ConfigSource for Stdin {
+    fn name(&self) -> &str {
+        "-"
+    }
+
+    fn read(&self) -> Result<Document, Error> {
+        let mut contents = String::new();
+        io::stdin()
+            .read_to_string(&mut contents)
+            .map_err(Error::Io)?;
+        Ok(Document {
+            contents,
+            format_hint: None,
+        })
+    }
+}
+
+/// An environment variable holding a whole document, for platforms that hand config to
+/// processes that way
+pub struct EnvVar {
+    var: String,
+    name: String,
+}
+
+impl EnvVar {
+    pub fn new(var: &str) -> Self {
+        Self {
+            var: var.to_string(),
+            name: format!("env:{var}"),
+        }
+    }
+}
+
+impl ConfigSource for EnvVar {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn read(&self) -> Result<Document, Error> {
+        let contents = std::env::var(&self.var).map_err(|e| {
+            let msg = format!("${}: {e}", self.var);
+            Error::Io(io::Error::new(io::ErrorKind::NotFound, msg))
+        })?;
+        Ok(Document {
+            contents,
+            format_hint: None,
+        })
+    }
+}
+
+/// A document that is already in memory, eg. embedded in the program or built by a test
+pub struct Inline {
+    name: String,
+    contents: String,
+    format_hint: Option<String>,
+    path: Option<PathBuf>,
+}
+
+impl Inline {
+    pub fn new(name: &str, contents: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            contents: contents.into(),
+            format_hint: None,
+            path: None,
+        }
+    }
+
+    /// Read the document as `extension`, instead of guessing from its contents
+    pub fn extension(mut self, extension: &str) -> Self {
+        self.format_hint = Some(extension.to_string());
+        self
+    }
+
+    /// Treat the document as if it was the file at `path`, eg. an edit of it that hasn't been
+    /// written yet, so its includes are found and its extension picks the format
+    pub fn at(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+}
+
+impl ConfigSource for Inline {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn read(&self) -> Result<Document, Error> {
+        Ok(Document {
+            contents: self.contents.clone(),
+            format_hint: self
+                .format_hint
+                .clone()
+                .or_else(|| {
+                    let extension = self.path.as_ref()?.extension()?;
+                    Some(extension.to_string_lossy().into_owned())
+                })
+                .map(FormatHint::Extension),
+        })
+    }
+
+    fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+}
+
+/// A document served over plain HTTP, like a config service on the local network. The format
+/// is taken from the `Content-Type`, or else the extension in the URL.
+///
+/// This is a deliberately small HTTP/1.0 client: no TLS, redirects or authentication.
+pub struct Http {
+    url: String,
+    /// `host:port`
+    authority: String,
+    path: String,
+}
+
+impl Http {
+    pub fn new(url: &str) -> Result<Self, Error> {
+        let Some(rest) = url.strip_prefix("http://") else {
+            let msg = format!("`{url}`: only plain http:// URLs are supported");
+            return Err(Error::Unsupported(msg));
+        };
+        let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        if authority.is_empty() {
+            return Err(Error::Usage(format!("`{url}` is missing a host")));
+        }
+        let authority = match authority.contains(':') {
+            true => authority.to_string(),
+            false => format!("{authority}:80"),
+        };
+        Ok(Self {
+            url: url.to_string(),
+            authority,
+            path: if path.is_empty() { "/" } else { path }.to_string(),
+        })
+    }
+
+    /// Try every address the host resolves to, like `TcpStream::connect`, but without waiting
+    /// for the OS to give up on one that doesn't answer
+    fn connect(&self) -> io::Result<TcpStream> {
+        let mut last = None;
+        for addr in self.authority.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, HTTP_TIMEOUT) {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last = Some(e),
+            }
+        }
+        Err(last.unwrap_or_else(|| {
+            let msg = format!("`{}` didn't resolve to any address", self.authority);
+            io::Error::new(io::ErrorKind::NotFound, msg)
+        }))
+    }
+
+    fn get(&self) -> io::Result<(String, Vec<u8>)> {
+        let mut stream = self.connect()?;
+        stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+        stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+        let host = self.authority.trim_end_matches(":80");
+        // In one go, as the stream isn't buffered
+        let request = format!(
+            "GET {} HTTP/1.0\r\nHost: {host}\r\nAccept: */*\r\n\r\n",
+            self.path
+        );
+        stream.write_all(request.as_bytes())?;
+
+        // HTTP/1.0 means the server closes the connection after the body, and doesn't chunk it
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response)?;
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let end = response
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .ok_or_else(|| invalid("the response has no end of headers"))?;
+        let head = String::from_utf8_lossy(&response[..end]).into_owned();
+        Ok((head, response[end + 4..].to_vec()))
+    }
+}
+
+impl ConfigSource for Http {
+    fn name(&self) -> &str {
+        &self.url
+    }
+
+    fn read(&self) -> Result<Document, Error> {
+        let (head, body) = self.get().map_err(Error::Io)?;
+        let mut lines = head.lines();
+        let status = lines.next().unwrap_or_default();
+        if !status
+            .split_whitespace()
+            .nth(1)
+            .is_some_and(|code| code.starts_with('2'))
+        {
+            return Err(Error::Io(io::Error::other(format!(
+                "the server answered `{status}`"
+            ))));
+        }
+        let content_type = lines.find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("content-type")
+                .then(|| value.trim().to_string())
+        });
+        let extension = || {
+            let file = self.path.split(['?', '#']).next()?.rsplit('/').next()?;
+            let (_, extension) = file.rsplit_once('.')?;
+            Some(FormatHint::Extension(extension.to_string()))
+        };
+
+        let contents = String::from_utf8(body)
+            .map_err(|e| Error::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        Ok(Document {
+            contents,
+            format_hint: content_type.map(FormatHint::MimeType).or_else(extension),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+        thread,
+    };
+
+    use super::*;
+
+    /// Serve `response` to the first request on a local port, and hand back the request
+    fn serve(response: &'static str) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            // The whole request, up to the blank line after the headers. Closing the connection
+            // with some of it unread would reset it, instead of sending the response.
+            let mut request = String::new();
+            let mut reader = BufReader::new(&stream);
+            while !request.ends_with("\r\n\r\n") {
+                if reader.read_line(&mut request).unwrap() == 0 {
+                    break;
+                }
+            }
+            stream.write_all(response.as_bytes()).unwrap();
+            request
+        });
+        (url, server)
+    }
+
+    #[test]
+    fn it_fetches_over_http() {
+        let (url, server) =
+            serve("HTTP/1.0 200 OK\r\nContent-Type: application/yaml\r\n\r\nport: 1234\n");
+        let document = Http::new(&format!("{url}/app/config"))
+            .unwrap()
+            .read()
+            .unwrap();
+        assert_eq!(document.contents, "port: 1234\n");
+        assert!(matches!(
+            document.format_hint,
+            Some(FormatHint::MimeType(m)) if m == "application/yaml"
+        ));
+        assert!(
+            server
+                .join()
+                .unwrap()
+                .starts_with("GET /app/config HTTP/1.0\r\n")
+        );
+
+        let (url, server) = serve("HTTP/1.0 200 OK\r\n\r\nport = 1234\n");
+        let document = Http::new(&format!("{url}/config.toml?v=2"))
+            .unwrap()
+            .read()
+            .unwrap();
+        assert!(matches!(
+            document.format_hint,
+            Some(FormatHint::Extension(e)) if e == "toml"
+        ));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn it_reports_http_errors() {
+        let (url, server) = serve("HTTP/1.0 404 Not Found\r\n\r\n");
+        let err = Http::new(&url).unwrap().read().err().unwrap();
+        assert_eq!(err.exit_code(), 3);
+        assert!(err.to_string().contains("404 Not Found"), "{err}");
+        server.join().unwrap();
+
+        assert_eq!(
+            Http::new("https://example.com").err().unwrap().exit_code(),
+            4
+        );
+    }
+
+    #[test]
+    fn it_reads_inline_documents_as_the_file_they_stand_for() {
+        let source = Inline::new("edit", "port = 1234\n").at("/etc/app/config.toml");
+        assert_eq!(source.path(), Some(Path::new("/etc/app/config.toml")));
+        assert!(matches!(
+            source.read().unwrap().format_hint,
+            Some(FormatHint::Extension(e)) if e == "toml"
+        ));
+        let source = source.extension("ini");
+        assert!(matches!(
+            source.read().unwrap().format_hint,
+            Some(FormatHint::Extension(e)) if e == "ini"
+        ));
+    }
+
+    #[test]
+    fn it_reads_documents_from_env_vars() {
+        let var = "CONFIG_READER_TEST_SOURCE";
+        // SAFETY: no other test touches this variable
+        unsafe { std::env::set_var(var, "port: 1234") };
+        let source = from_input(&format!("env:{var}")).unwrap();
+        assert_eq!(source.name(), "env:CONFIG_READER_TEST_SOURCE");
+        assert_eq!(source.read().unwrap().contents, "port: 1234");
+
+        let err = EnvVar::new("CONFIG_READER_TEST_UNSET")
+            .read()
+            .err()
+            .unwrap();
+        assert_eq!(err.exit_code(), 3);
+    }
+}
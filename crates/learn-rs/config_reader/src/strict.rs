@@ -0,0 +1,386 @@
+use std::{fmt, marker::PhantomData, str::FromStr};
+
+use serde::{
+    Deserialize, Deserializer,
+    de::{self, DeserializeSeed, MapAccess, SeqAccess, Unexpected, Visitor},
+};
+use serde_json::Value;
+
+use crate::{
+    ConfigDeserializer, Error,
+    error::is_similar,
+    include::INCLUDE_KEY,
+    interpolate::VARS_KEY,
+    migrate::{self, VERSION_KEY},
+    profile,
+    schema::{Violation, escape},
+};
+
+/// Check `contents` against `schema` more strictly than deserializing does: keys the schema
+/// doesn't know about, keys that appear twice in the same map, and quoted scalars that only
+/// deserialize because we coerce them (eg. `port: "1234"`) are all reported.
+///
+/// Duplicates are found by walking the document as the format hands it to serde, so every
+/// `ConfigDeserializer` is held to the same rules. Each section of a profiled document is checked
+/// against `schema` separately.
+pub fn audit(
+    format: &dyn ConfigDeserializer,
+    contents: &str,
+    schema: &Value,
+) -> Result<Vec<Violation>, Error> {
+    let DuplicateKeys(mut violations) = format.deserialize(contents)?;
+    let mut document: Value = format.deserialize(contents)?;
+    let version = migrate::version(&document)?;
+    // Not part of the config itself, they're gone by the time it's deserialized
+    if let Some(map) = document.as_object_mut() {
+        map.remove(INCLUDE_KEY);
+        map.remove(VARS_KEY);
+        map.remove(VERSION_KEY);
+    }
+    let mut auditor = Auditor {
+        root: schema,
+        violations: &mut violations,
+    };
+    // Every profile is (part of) a config on its own. Older layouts are checked as if they had
+    // been migrated.
+    let profiled = profile::is_profiled(&document);
+    match document.as_object_mut().filter(|_| profiled) {
+        Some(sections) => {
+            for (name, section) in sections {
+                migrate::apply(section, version);
+                auditor.check(schema, section, &format!("/{}", escape(name)));
+            }
+        }
+        None => {
+            migrate::apply(&mut document, version);
+            auditor.check(schema, &document, "");
+        }
+    }
+    Ok(violations)
+}
+
+/// `audit` against the `Config` schema, turning whatever it finds into a single error
+pub fn check(format: &dyn ConfigDeserializer, contents: &str) -> Result<(), Error> {
+    let violations = audit(format, contents, &crate::schema::config_schema())?;
+    if violations.is_empty() {
+        return Ok(());
+    }
+    let mut msg = format!("strict mode found {} problem(s):", violations.len());
+    for violation in &violations {
+        msg.push_str(&format!("\n    {violation}"));
+    }
+    Err(Error::Invalid(msg))
+}
+
+/// `deserialize_with` for numbers that may have been written as strings, eg. `port: "1234"`.
+/// Typed formats only end up with a string here when it was quoted on purpose, while untyped
+/// ones (INI) leave quoted values alone, so this is what keeps them all behaving the same.
+pub fn lenient_number<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr + TryFrom<u64> + TryFrom<i64>,
+{
+    struct NumberVisitor<T>(PhantomData<T>);
+
+    impl<T: FromStr + TryFrom<u64> + TryFrom<i64>> Visitor<'_> for NumberVisitor<T> {
+        type Value = T;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a number")
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<T, E> {
+            T::try_from(v).map_err(|_| E::invalid_value(Unexpected::Unsigned(v), &self))
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<T, E> {
+            T::try_from(v).map_err(|_| E::invalid_value(Unexpected::Signed(v), &self))
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<T, E> {
+            v.trim()
+                .parse()
+                .map_err(|_| E::invalid_value(Unexpected::Str(v), &self))
+        }
+    }
+
+    deserializer.deserialize_any(NumberVisitor(PhantomData))
+}
+
+/// Every key that shows up more than once in the same map, as violations
+struct DuplicateKeys(Vec<Violation>);
+
+impl<'de> Deserialize<'de> for DuplicateKeys {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut found = Vec::new();
+        deserializer.deserialize_any(KeyWalker {
+            pointer: String::new(),
+            found: &mut found,
+        })?;
+        Ok(DuplicateKeys(found))
+    }
+}
+
+/// Walks a whole document, recording duplicate keys as it goes
+struct KeyWalker<'f> {
+    pointer: String,
+    found: &'f mut Vec<Violation>,
+}
+
+impl<'de> DeserializeSeed<'de> for KeyWalker<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for KeyWalker<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "anything")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_unit<E>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_none<E>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let mut i = 0;
+        while seq
+            .next_element_seed(KeyWalker {
+                pointer: format!("{}/{i}", self.pointer),
+                found: &mut *self.found,
+            })?
+            .is_some()
+        {
+            i += 1;
+        }
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let mut seen = Vec::new();
+        // Keys aren't always strings in YAML, so take whatever they are and print it
+        while let Some(key) = map.next_key::<Value>()? {
+            let key = match key {
+                Value::String(s) => s,
+                other => other.to_string(),
+            };
+            if seen.contains(&key) {
+                self.found.push(Violation {
+                    pointer: self.pointer.clone(),
+                    message: format!("duplicate key `{key}`"),
+                });
+            }
+            map.next_value_seed(KeyWalker {
+                pointer: format!("{}/{}", self.pointer, escape(&key)),
+                found: &mut *self.found,
+            })?;
+            seen.push(key);
+        }
+        Ok(())
+    }
+}
+
+/// Walks a parsed document alongside its schema, looking for unknown keys and coerced scalars.
+/// Checking values themselves is left to `schema::validate`.
+struct Auditor<'s, 'v> {
+    root: &'s Value,
+    violations: &'v mut Vec<Violation>,
+}
+
+impl<'s> Auditor<'s, '_> {
+    fn check(&mut self, schema: &'s Value, instance: &Value, pointer: &str) {
+        let schema = self.resolve(schema);
+        let types = self.types(schema);
+
+        match instance {
+            Value::String(s) if !types.is_empty() && !types.contains(&"string") => {
+                if let Some(t) = types.iter().find(|t| parses_as(s, t)) {
+                    self.violations.push(Violation {
+                        pointer: pointer.to_string(),
+                        message: format!("`\"{s}\"` is quoted, but should be a(n) {t}"),
+                    });
+                }
+            }
+            Value::Object(object) => {
+                let properties = schema.get("properties").and_then(Value::as_object);
+                // Maps without any declared properties take whatever keys they're given
+                let Some(properties) = properties else {
+                    return;
+                };
+                for (key, value) in object {
+                    match properties.get(key) {
+                        Some(property) => {
+                            self.check(property, value, &format!("{pointer}/{}", escape(key)))
+                        }
+                        None => self.violations.push(Violation {
+                            pointer: pointer.to_string(),
+                            message: unknown_key(key, properties.keys()),
+                        }),
+                    }
+                }
+            }
+            Value::Array(values) => {
+                if let Some(items) = schema.get("items") {
+                    for (i, value) in values.iter().enumerate() {
+                        self.check(items, value, &format!("{pointer}/{i}"));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Follow local `$ref`s until we end up at an actual schema
+    fn resolve(&self, mut schema: &'s Value) -> &'s Value {
+        while let Some(target) = schema
+            .get("$ref")
+            .and_then(Value::as_str)
+            .and_then(|r| r.strip_prefix('#'))
+            .and_then(|pointer| self.root.pointer(pointer))
+        {
+            schema = target;
+        }
+        schema
+    }
+
+    /// The types `schema` allows, including those allowed by any of its `anyOf`/`oneOf` branches
+    fn types(&self, schema: &'s Value) -> Vec<&'s str> {
+        let mut types = match schema.get("type") {
+            Some(Value::String(t)) => vec![t.as_str()],
+            Some(Value::Array(ts)) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        for combinator in ["anyOf", "oneOf"] {
+            if let Some(Value::Array(branches)) = schema.get(combinator) {
+                for branch in branches {
+                    types.extend(self.types(self.resolve(branch)));
+                }
+            }
+        }
+        types
+    }
+}
+
+/// Whether `s` would make sense as a value of JSON Schema type `t` if it wasn't quoted
+fn parses_as(s: &str, t: &str) -> bool {
+    let s = s.trim();
+    match t {
+        "integer" => s.parse::<i64>().is_ok() || s.parse::<u64>().is_ok(),
+        "number" => s.parse::<f64>().is_ok(),
+        "boolean" => s.parse::<bool>().is_ok(),
+        _ => false,
+    }
+}
+
+fn unknown_key<'k>(key: &str, mut known: impl Iterator<Item = &'k String>) -> String {
+    match known.find(|k| is_similar(key, k)) {
+        Some(k) => format!("unknown key `{key}`, did you mean `{k}`?"),
+        None => format!("unknown key `{key}`"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::FormatRegistry;
+
+    fn audited(format: &str, contents: &str) -> Vec<String> {
+        let registry = FormatRegistry::default();
+        let schema = crate::schema::config_schema();
+        audit(
+            registry.by_name(format).unwrap().as_ref(),
+            contents,
+            &schema,
+        )
+        .unwrap()
+        .iter()
+        .map(Violation::to_string)
+        .collect()
+    }
+
+    #[test]
+    fn it_passes_the_fixtures() {
+        assert!(audited("json", include_str!("../config.json")).is_empty());
+        assert!(audited("yaml", include_str!("../config.yml")).is_empty());
+        assert!(audited("toml", include_str!("../config.toml")).is_empty());
+        assert!(audited("ini", include_str!("../config.ini")).is_empty());
+    }
+
+    #[test]
+    fn it_reports_the_same_problems_for_every_format() {
+        let expected = [
+            "/port: `\"1234\"` is quoted, but should be a(n) integer",
+            "/: unknown key `base_ulr`, did you mean `base_urls`?",
+        ];
+        let formats = [
+            ("json", r#"{"port": "1234", "base_ulr": "https://x"}"#),
+            ("yaml", "port: \"1234\"\nbase_ulr: https://x\n"),
+            ("toml", "port = \"1234\"\nbase_ulr = \"https://x\"\n"),
+            ("ini", "port = \"1234\"\nbase_ulr = https://x\n"),
+        ];
+        for (format, contents) in formats {
+            assert_eq!(audited(format, contents), expected, "{format}");
+        }
+    }
+
+    #[test]
+    fn it_reports_duplicate_keys() {
+        let expected = ["/: duplicate key `port`"];
+        assert_eq!(audited("json", r#"{"port": 1, "port": 2}"#), expected);
+        assert_eq!(audited("yaml", "port: 1\nport: 2\n"), expected);
+        assert_eq!(audited("ini", "port = 1\nport = 2\n"), expected);
+    }
+
+    #[test]
+    fn it_coerces_quoted_numbers_when_not_strict() {
+        #[derive(Deserialize)]
+        struct Port {
+            #[serde(deserialize_with = "lenient_number")]
+            port: u16,
+        }
+
+        let registry = FormatRegistry::default();
+        for (format, contents) in [("json", r#"{"port": "1234"}"#), ("ini", "port = 1234")] {
+            let parsed: Port = registry
+                .by_name(format)
+                .unwrap()
+                .deserialize(contents)
+                .unwrap();
+            assert_eq!(parsed.port, 1234, "{format}");
+        }
+        let too_big = registry.by_name("json").unwrap();
+        assert!(too_big.deserialize::<Port>(r#"{"port": 70000}"#).is_err());
+    }
+}
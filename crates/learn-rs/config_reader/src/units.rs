@@ -0,0 +1,289 @@
+use std::{borrow::Cow, fmt, str::FromStr, time::Duration};
+
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
+use serde::{
+    Deserialize, Deserializer, Serialize, Serializer,
+    de::{self, Visitor},
+};
+
+/// A number of bytes, written as `10MiB`, `512KB`, `1.5GiB` or just a number of bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteSize(pub u64);
+
+const BYTE_UNITS: [(&str, u64); 9] = [
+    ("TiB", 1 << 40),
+    ("GiB", 1 << 30),
+    ("MiB", 1 << 20),
+    ("KiB", 1 << 10),
+    ("TB", 1_000_000_000_000),
+    ("GB", 1_000_000_000),
+    ("MB", 1_000_000),
+    ("KB", 1_000),
+    ("B", 1),
+];
+
+impl ByteSize {
+    pub const fn kib(n: u64) -> Self {
+        Self(n << 10)
+    }
+
+    pub const fn mib(n: u64) -> Self {
+        Self(n << 20)
+    }
+}
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (number, unit) = split_unit(s);
+        let multiplier = match unit {
+            "" => 1,
+            unit => BYTE_UNITS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(unit))
+                .map(|(_, multiplier)| *multiplier)
+                .ok_or_else(|| format!("unknown size unit `{unit}` in `{s}`"))?,
+        };
+        let bytes = parse_number(number, s)? * multiplier as f64;
+        if bytes.fract() != 0.0 || bytes > u64::MAX as f64 {
+            return Err(format!("`{s}` isn't a whole number of bytes"));
+        }
+        Ok(Self(bytes as u64))
+    }
+}
+
+/// The largest binary unit the size is a whole multiple of, so sizes read back the same
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (unit, multiplier) = BYTE_UNITS
+            .iter()
+            .filter(|(unit, _)| unit.ends_with("iB") || *unit == "B")
+            .find(|(_, multiplier)| self.0 != 0 && self.0.is_multiple_of(*multiplier))
+            .unwrap_or(&("B", 1));
+        write!(f, "{}{unit}", self.0 / multiplier)
+    }
+}
+
+impl Serialize for ByteSize {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(UnitVisitor("a size like `10MiB`", str::parse))
+    }
+}
+
+impl JsonSchema for ByteSize {
+    fn schema_name() -> Cow<'static, str> {
+        "ByteSize".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "description": "A size like `10MiB` or `512KB`, or a number of bytes",
+            "type": ["string", "integer"],
+            "minimum": 0,
+        })
+    }
+}
+
+/// A `Duration` written as `30s`, `500ms`, `1h30m` or just a number of seconds, for
+/// `#[serde(with = "units::duration")]`
+pub mod duration {
+    use super::*;
+
+    const UNITS: [(&str, Duration); 5] = [
+        ("d", Duration::from_secs(24 * 60 * 60)),
+        ("h", Duration::from_secs(60 * 60)),
+        ("m", Duration::from_secs(60)),
+        ("s", Duration::from_secs(1)),
+        ("ms", Duration::from_millis(1)),
+    ];
+
+    pub fn parse(s: &str) -> Result<Duration, String> {
+        let too_long = || format!("`{s}` is longer than a duration can be");
+        let mut rest = s.trim();
+        if rest.chars().all(|c| c.is_ascii_digit() || c == '.') {
+            return Duration::try_from_secs_f64(parse_number(rest, s)?).map_err(|_| too_long());
+        }
+
+        let mut total = Duration::ZERO;
+        while !rest.is_empty() {
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .ok_or_else(|| format!("`{s}` is missing a unit, like `s` or `ms`"))?;
+            let letters = rest[digits..]
+                .find(|c: char| c.is_ascii_digit())
+                .map_or(rest.len(), |i| digits + i);
+            let (number, unit) = (&rest[..digits], rest[digits..letters].trim());
+            let Some((_, length)) = UNITS.iter().find(|(name, _)| *name == unit) else {
+                return Err(format!("unknown duration unit `{unit}` in `{s}`"));
+            };
+            let part = Duration::try_from_secs_f64(length.as_secs_f64() * parse_number(number, s)?);
+            total = part
+                .ok()
+                .and_then(|part| total.checked_add(part))
+                .ok_or_else(too_long)?;
+            rest = rest[letters..].trim_start();
+        }
+        Ok(total)
+    }
+
+    /// `duration` in the largest units that make it up exactly, eg. `1h30m`
+    pub fn format(duration: Duration) -> String {
+        if duration.is_zero() {
+            return "0s".to_string();
+        }
+        let mut out = String::new();
+        let mut rest = duration.as_nanos();
+        for (name, length) in UNITS {
+            let count = rest / length.as_nanos();
+            if count > 0 {
+                out.push_str(&format!("{count}{name}"));
+                rest -= count * length.as_nanos();
+            }
+        }
+        // Whatever is left is less than a millisecond
+        if rest > 0 {
+            out.push_str(&format!("{rest}ns"));
+        }
+        out
+    }
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format(*duration))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        deserializer.deserialize_any(UnitVisitor("a duration like `30s`", parse))
+    }
+
+    /// For `#[schemars(schema_with = "units::duration::schema")]`
+    pub fn schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "description": "A duration like `30s`, `500ms` or `1h30m`, or a number of seconds",
+            "type": ["string", "integer"],
+            "minimum": 0,
+        })
+    }
+}
+
+/// Takes the unit apart from the number, `10MiB` -> (`10`, `MiB`)
+fn split_unit(s: &str) -> (&str, &str) {
+    let s = s.trim();
+    let end = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    (&s[..end], s[end..].trim())
+}
+
+fn parse_number(number: &str, whole: &str) -> Result<f64, String> {
+    number
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite() && *n >= 0.0)
+        .ok_or_else(|| format!("`{whole}` doesn't start with a number"))
+}
+
+/// Takes a string with a unit, or a bare number in the base unit
+struct UnitVisitor<T>(&'static str, fn(&str) -> Result<T, String>);
+
+impl<T> Visitor<'_> for UnitVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.0)
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<T, E> {
+        self.visit_str(&v.to_string())
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<T, E> {
+        self.visit_str(&v.to_string())
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<T, E> {
+        self.visit_str(&v.to_string())
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<T, E> {
+        (self.1)(v).map_err(E::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_sizes() {
+        assert_eq!("10MiB".parse(), Ok(ByteSize::mib(10)));
+        assert_eq!("512 kb".parse(), Ok(ByteSize(512_000)));
+        assert_eq!("1.5KiB".parse(), Ok(ByteSize(1536)));
+        assert_eq!("42".parse(), Ok(ByteSize(42)));
+        assert!("10 parsecs".parse::<ByteSize>().is_err());
+        assert!("0.5B".parse::<ByteSize>().is_err());
+    }
+
+    #[test]
+    fn it_writes_sizes_in_the_largest_exact_unit() {
+        assert_eq!(ByteSize::mib(10).to_string(), "10MiB");
+        assert_eq!(ByteSize(1536).to_string(), "1536B");
+        assert_eq!(ByteSize(0).to_string(), "0B");
+    }
+
+    #[test]
+    fn it_parses_durations() {
+        assert_eq!(duration::parse("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(duration::parse("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(duration::parse("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(duration::parse("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(duration::parse("90"), Ok(Duration::from_secs(90)));
+        assert!(duration::parse("30 fortnights").is_err());
+        assert!(duration::parse("s").is_err());
+    }
+
+    #[test]
+    fn it_rejects_durations_that_overflow() {
+        let too_long = "`99999999999999999999d` is longer than a duration can be";
+        assert_eq!(
+            duration::parse("99999999999999999999d").unwrap_err(),
+            too_long
+        );
+        assert!(duration::parse("99999999999999999999").is_err());
+        // Each part fits, but not both together
+        assert!(duration::parse("10000000000000000000s").is_ok());
+        assert!(duration::parse("10000000000000000000s10000000000000000000s").is_err());
+    }
+
+    #[test]
+    fn it_round_trips_durations() {
+        for s in ["30s", "1h30m", "250ms", "2d1s", "0s"] {
+            assert_eq!(duration::format(duration::parse(s).unwrap()), s);
+        }
+    }
+
+    #[test]
+    fn it_deserializes_strings_and_numbers() {
+        #[derive(Deserialize, Debug)]
+        struct Limits {
+            #[serde(with = "duration")]
+            timeout: Duration,
+            size: ByteSize,
+        }
+
+        let limits: Limits = serde_json::from_str(r#"{"timeout": 5, "size": "1KiB"}"#).unwrap();
+        assert_eq!(limits.timeout, Duration::from_secs(5));
+        assert_eq!(limits.size, ByteSize::kib(1));
+        let err = serde_json::from_str::<Limits>(r#"{"timeout": "5x", "size": 1}"#).unwrap_err();
+        assert!(
+            err.to_string().contains("unknown duration unit `x`"),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+
+use crate::{
+    ConfigSource, OwnedConfig,
+    error::{ConfigError, Error},
+    load::{LoadOptions, load_sources},
+    registry::FormatRegistry,
+    source,
+};
+
+/// What subscribers of a `ConfigWatcher` get told about
+#[derive(Debug, Clone)]
+pub enum ReloadEvent {
+    /// The file changed and the new config is now current
+    Updated(Arc<OwnedConfig>),
+    /// The file changed but the new config is broken, so the previous one stays current
+    Rejected(String),
+}
+
+/// Polls a config file and reloads it whenever its contents change.
+///
+/// Every reload goes through `load_sources`, so profiles, includes, interpolation, environment
+/// overrides and strict mode apply the same as they do to a one-off load. Only the file itself is
+/// polled, changes to what it includes are picked up the next time it changes.
+///
+/// A config that fails to parse or validate never replaces the current one. The polling thread is
+/// stopped when the watcher is dropped.
+pub struct ConfigWatcher {
+    current: Arc<Mutex<Arc<OwnedConfig>>>,
+    subscribers: Arc<Mutex<Vec<Sender<ReloadEvent>>>>,
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ConfigWatcher {
+    /// Load `path` with `options` and start watching it. Fails if the initial config can't be
+    /// loaded, as there would be nothing to fall back on.
+    pub fn spawn(
+        path: PathBuf,
+        registry: FormatRegistry,
+        options: LoadOptions,
+        interval: Duration,
+    ) -> Result<Self, ConfigError> {
+        let mut last = std::fs::read_to_string(&path)
+            .map_err(|e| ConfigError::from(Error::Io(e)).with_path(&path.display().to_string()))?;
+        let config = load(&registry, &path, &last, &options)?;
+
+        let current = Arc::new(Mutex::new(Arc::new(config)));
+        let subscribers = Arc::new(Mutex::new(Vec::<Sender<ReloadEvent>>::new()));
+        let (stop, stopped) = crossbeam_channel::bounded::<()>(0);
+
+        let handle = std::thread::spawn({
+            let current = Arc::clone(&current);
+            let subscribers = Arc::clone(&subscribers);
+            move || {
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    // The file may be mid-rewrite or briefly gone, try again next time around
+                    let Ok(contents) = std::fs::read_to_string(&path) else {
+                        continue;
+                    };
+                    if contents == last {
+                        continue;
+                    }
+
+                    let event = match load(&registry, &path, &contents, &options) {
+                        Ok(config) => {
+                            let config = Arc::new(config);
+                            *current.lock().unwrap() = Arc::clone(&config);
+                            ReloadEvent::Updated(config)
+                        }
+                        Err(e) => ReloadEvent::Rejected(e.to_string()),
+                    };
+                    last = contents;
+
+                    // Forget about subscribers that went away
+                    subscribers
+                        .lock()
+                        .unwrap()
+                        .retain(|s| s.send(event.clone()).is_ok());
+                }
+            }
+        });
+
+        Ok(Self {
+            current,
+            subscribers,
+            stop: Some(stop),
+            handle: Some(handle),
+        })
+    }
+
+    /// The most recent config that parsed and validated
+    pub fn current(&self) -> Arc<OwnedConfig> {
+        Arc::clone(&self.current.lock().unwrap())
+    }
+
+    /// Get told about every reload from now on
+    pub fn subscribe(&self) -> Receiver<ReloadEvent> {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+}
+
+impl Drop for ConfigWatcher {
+    fn drop(&mut self) {
+        // Disconnecting the stop channel wakes the polling thread up for good
+        drop(self.stop.take());
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Load `contents`, as read from `path`. Reading the file again could get a newer version than
+/// the one that was compared with the last.
+fn load(
+    registry: &FormatRegistry,
+    path: &Path,
+    contents: &str,
+    options: &LoadOptions,
+) -> Result<OwnedConfig, ConfigError> {
+    let source = source::Inline::new(&path.display().to_string(), contents).at(path);
+    let sources: [Box<dyn ConfigSource>; 1] = [Box::new(source)];
+    Ok(load_sources(registry, &sources, options.clone())?.config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn watch(path: &Path, options: LoadOptions) -> Result<ConfigWatcher, ConfigError> {
+        let registry = FormatRegistry::default();
+        ConfigWatcher::spawn(
+            path.to_path_buf(),
+            registry,
+            options,
+            Duration::from_millis(10),
+        )
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("config_reader_{}_{name}", std::process::id()))
+    }
+
+    #[test]
+    fn it_publishes_reloads_and_keeps_the_last_good_config() {
+        let path = temp_path("watch.yml");
+        let original = include_str!("../config.yml");
+        std::fs::write(&path, original).unwrap();
+
+        let watcher = watch(&path, LoadOptions::default()).unwrap();
+        let events = watcher.subscribe();
+        assert_eq!(watcher.current().port, 1234);
+
+        std::fs::write(&path, original.replace("1234", "8080")).unwrap();
+        match events.recv_timeout(TIMEOUT).unwrap() {
+            ReloadEvent::Updated(config) => assert_eq!(config.port, 8080),
+            other => panic!("expected an update, got {other:?}"),
+        }
+        assert_eq!(watcher.current().port, 8080);
+
+        std::fs::write(&path, "port: [not a port").unwrap();
+        assert!(matches!(
+            events.recv_timeout(TIMEOUT).unwrap(),
+            ReloadEvent::Rejected(_)
+        ));
+
+        // Parses, but doesn't validate
+        std::fs::write(&path, original.replace("1234", "0")).unwrap();
+        assert!(matches!(
+            events.recv_timeout(TIMEOUT).unwrap(),
+            ReloadEvent::Rejected(_)
+        ));
+        assert_eq!(watcher.current().port, 8080);
+
+        drop(watcher);
+        assert!(events.recv_timeout(TIMEOUT).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn it_reloads_the_same_way_it_loads() {
+        let path = temp_path("profiles.yml");
+        let profiled = "vars:\n  host: teach-rs.tweede.golf\n\
+                        default:\n  port: 1234\n  base_url: https://${host}\n  \
+                        s3_path: bucket.${host}\n  database_url: postgresql://db/app\n\
+                        prod:\n  port: 443\n";
+        std::fs::write(&path, profiled).unwrap();
+
+        let options = LoadOptions {
+            profile: Some("prod".to_string()),
+            strict: true,
+            ..LoadOptions::default()
+        };
+        let watcher = watch(&path, options).unwrap();
+        let events = watcher.subscribe();
+        assert_eq!(watcher.current().port, 443);
+        assert_eq!(watcher.current().base_url(), "https://teach-rs.tweede.golf");
+
+        std::fs::write(&path, profiled.replace("teach-rs", "example")).unwrap();
+        match events.recv_timeout(TIMEOUT).unwrap() {
+            ReloadEvent::Updated(config) => {
+                assert_eq!(config.s3.bucket, "bucket.example.tweede.golf")
+            }
+            other => panic!("expected an update, got {other:?}"),
+        }
+
+        // Strict mode still holds
+        std::fs::write(&path, profiled.replace("port: 443", "port: \"443\"")).unwrap();
+        match events.recv_timeout(TIMEOUT).unwrap() {
+            ReloadEvent::Rejected(e) => assert!(e.contains("quoted"), "{e}"),
+            other => panic!("expected a rejection, got {other:?}"),
+        }
+
+        drop(watcher);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn it_needs_an_initial_config() {
+        let path = temp_path("missing.yml");
+        assert!(watch(&path, LoadOptions::default()).is_err());
+    }
+}
@@ -0,0 +1,341 @@
+//! Runs the `learn_rs_config_reader` binary the way a user would
+
+use assert_cmd::{Command, cargo::cargo_bin_cmd};
+use predicates::prelude::*;
+
+const FIXTURES: [&str; 4] = ["config.json", "config.yml", "config.toml", "config.ini"];
+
+fn fixture(name: &str) -> String {
+    format!("{}/{name}", env!("CARGO_MANIFEST_DIR"))
+}
+
+fn cli() -> Command {
+    let mut cmd = cargo_bin_cmd!("learn_rs_config_reader");
+    cmd.env_remove("CONFIG_PROFILE");
+    cmd
+}
+
+#[test]
+fn it_parses_every_fixture() {
+    for name in FIXTURES {
+        cli()
+            .arg(fixture(name))
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("port: 1234"))
+            .stdout(predicate::str::contains("postgresql://").not());
+        cli()
+            .args(["parse", &fixture(name)])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("port: 1234"));
+    }
+}
+
+#[test]
+fn it_reads_stdin() {
+    cli()
+        .arg("-")
+        .pipe_stdin(fixture("config.yml"))
+        .unwrap()
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("port: 1234"));
+}
+
+#[test]
+fn it_reads_env_vars() {
+    let contents = std::fs::read_to_string(fixture("config.json")).unwrap();
+    cli()
+        .args(["get", "port", "env:CONFIG_DOCUMENT"])
+        .env("CONFIG_DOCUMENT", contents)
+        .assert()
+        .success()
+        .stdout("1234\n");
+    cli()
+        .args(["parse", "env:CONFIG_DOCUMENT"])
+        .env_remove("CONFIG_DOCUMENT")
+        .assert()
+        .code(3)
+        .stderr(predicate::str::contains("env:CONFIG_DOCUMENT"));
+}
+
+#[test]
+fn it_lists_formats() {
+    let listing = cli().arg("formats").assert().success();
+    let listing = String::from_utf8(listing.get_output().stdout.clone()).unwrap();
+    assert!(listing.contains("application/toml"), "{listing}");
+    // The flag from before there were subcommands still works
+    cli()
+        .arg("--list-formats")
+        .assert()
+        .success()
+        .stdout(listing);
+}
+
+#[test]
+fn it_prints_json_and_yaml() {
+    let output = cli()
+        .args(["parse", &fixture("config.toml"), "--output", "json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let config: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(config["port"], 1234);
+    assert_eq!(config["database"]["url"], "[REDACTED]");
+
+    cli()
+        .args(["parse", &fixture("config.json"), "--output", "yaml"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("port: 1234\n"))
+        .stdout(predicate::str::contains("url: '[REDACTED]'"));
+}
+
+#[test]
+fn it_gets_single_values() {
+    for name in FIXTURES {
+        cli()
+            .args(["get", "port", &fixture(name)])
+            .assert()
+            .success()
+            .stdout("1234\n");
+        cli()
+            .args(["get", "/s3/bucket", &fixture(name)])
+            .assert()
+            .success()
+            .stdout("bucket.teach-rs.tweede.golf\n");
+    }
+    cli()
+        .args(["get", "port", &fixture("config.yml")])
+        .env("APP_PORT", "80")
+        .assert()
+        .success()
+        .stdout("80\n");
+    cli()
+        .args(["get", "database.url", &fixture("config.yml")])
+        .assert()
+        .success()
+        .stdout("[REDACTED]\n");
+    cli()
+        .args(["get", "prot", &fixture("config.yml")])
+        .assert()
+        .code(7)
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "`prot` isn't set, did you mean `port`?",
+        ));
+}
+
+#[test]
+fn it_documents_exit_codes_in_help() {
+    cli()
+        .arg("--help")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Exit codes:"))
+        .stdout(predicate::str::contains("convert"));
+}
+
+#[test]
+fn it_exits_with_the_class_of_error() {
+    cli()
+        .arg("does-not-exist.yml")
+        .assert()
+        .code(3)
+        .stderr(predicate::str::contains("does-not-exist.yml"));
+    cli().assert().code(2);
+    cli().arg("--format").assert().code(2);
+    cli()
+        .args(["--format", "xml", "config.yml"])
+        .assert()
+        .code(2);
+    cli()
+        .args(["validate", "-"])
+        .write_stdin("{\"port\": ")
+        .assert()
+        .code(5);
+    cli()
+        .args(["validate", "-", "--format", "json"])
+        .write_stdin("{\"port\": \"high\"}")
+        .assert()
+        .code(6);
+}
+
+#[test]
+fn it_converts_between_formats() {
+    cli()
+        .args(["convert", &fixture("config.yml"), "--to", "toml"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("port = 1234"));
+}
+
+#[test]
+fn it_selects_profiles_from_the_environment() {
+    let dir =
+        std::env::temp_dir().join(format!("config_reader_cli_profiles_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.yml");
+    let base = std::fs::read_to_string(fixture("config.yml")).unwrap();
+    let indented: String = base.lines().map(|line| format!("  {line}\n")).collect();
+    std::fs::write(&path, format!("default:\n{indented}prod:\n  port: 443\n")).unwrap();
+    let path = path.to_str().unwrap();
+
+    // Every subcommand reads `CONFIG_PROFILE` the same way
+    for args in [
+        vec!["get", "port", path],
+        vec!["convert", path, "--to", "json"],
+    ] {
+        cli()
+            .args(&args)
+            .env("CONFIG_PROFILE", "prod")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("443"));
+    }
+    cli()
+        .args(["diff", &fixture("config.yml"), path])
+        .env("CONFIG_PROFILE", "prod")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("port"));
+    cli()
+        .args(["diff", &fixture("config.yml"), path])
+        .assert()
+        .success()
+        .stdout("");
+}
+
+#[test]
+fn it_migrates_configs_in_place() {
+    let dir = std::env::temp_dir().join(format!("config_reader_cli_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.yml");
+    std::fs::copy(fixture("config.yml"), &path).unwrap();
+    let path = path.to_str().unwrap();
+
+    cli().args(["migrate", "--check", path]).assert().code(6);
+    cli()
+        .args(["migrate", path])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("migrated from version 1 to 2"));
+    let migrated = std::fs::read_to_string(path).unwrap();
+    assert!(
+        migrated.starts_with("version: 2\nport: 1234\n"),
+        "{migrated}"
+    );
+    assert!(migrated.contains("s3:\n  bucket: bucket.teach-rs.tweede.golf\n"));
+
+    cli()
+        .args(["migrate", "--check", path])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("already at version 2"));
+    cli()
+        .args(["get", "s3.bucket", path])
+        .assert()
+        .success()
+        .stdout("bucket.teach-rs.tweede.golf\n");
+}
+
+#[test]
+fn it_sets_values_in_place() {
+    let dir = std::env::temp_dir().join(format!("config_reader_cli_set_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let yaml = dir.join("config.yml");
+    let original = format!(
+        "# Maintained by hand\n{}",
+        std::fs::read_to_string(fixture("config.yml")).unwrap()
+    );
+    std::fs::write(&yaml, &original).unwrap();
+    let yaml = yaml.to_str().unwrap();
+
+    cli()
+        .args(["set", "port", "8080", yaml])
+        .assert()
+        .success()
+        .stdout("");
+    let edited = std::fs::read_to_string(yaml).unwrap();
+    assert_eq!(edited, original.replace("port: 1234", "port: 8080"));
+    cli()
+        .args(["get", "port", yaml])
+        .assert()
+        .success()
+        .stdout("8080\n");
+    cli()
+        .args(["set", "s3_path", "0123", "--string", yaml])
+        .assert()
+        .success();
+    cli()
+        .args(["get", "s3.bucket", yaml])
+        .assert()
+        .success()
+        .stdout("0123\n");
+
+    // Edits that would leave a config nothing can load are refused, and the file left alone
+    let before = std::fs::read_to_string(yaml).unwrap();
+    cli()
+        .args(["set", "port", "abc", yaml])
+        .assert()
+        .code(5)
+        .stderr(predicate::str::contains("port"));
+    assert_eq!(std::fs::read_to_string(yaml).unwrap(), before);
+
+    // serde_json keeps the last `port`, where the editor would find the first
+    let json = dir.join("dup.json");
+    let duplicated =
+        r#"{"version": 2, "port": {"x": 1}, "port": 1234, "base_urls": ["https://a.example"]}"#;
+    std::fs::write(&json, duplicated).unwrap();
+    cli()
+        .args(["set", "port.x", "2", json.to_str().unwrap()])
+        .assert()
+        .code(6)
+        .stderr(predicate::str::contains("`port` is set more than once"));
+    assert_eq!(std::fs::read_to_string(&json).unwrap(), duplicated);
+
+    let toml = dir.join("config.toml");
+    std::fs::copy(fixture("config.toml"), &toml).unwrap();
+    cli()
+        .args(["set", "port", "8080", toml.to_str().unwrap()])
+        .assert()
+        .code(4)
+        .stderr(predicate::str::contains("can't be edited in place"));
+}
+
+#[test]
+fn it_validates_against_the_schema() {
+    cli()
+        .args(["validate", &fixture("config.json")])
+        .assert()
+        .success();
+
+    // `validate` agrees with `parse` about a config without any URLs
+    let dir =
+        std::env::temp_dir().join(format!("config_reader_cli_validate_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let empty = dir.join("config.yml");
+    let contents = std::fs::read_to_string(fixture("config.yml")).unwrap();
+    let urls = contents.find("base_url:").unwrap();
+    let line_end = urls + contents[urls..].find('\n').unwrap();
+    std::fs::write(
+        &empty,
+        format!("{}base_url: []{}", &contents[..urls], &contents[line_end..]),
+    )
+    .unwrap();
+    let empty = empty.to_str().unwrap();
+    cli().args(["parse", empty]).assert().code(6);
+    cli()
+        .args(["validate", empty])
+        .assert()
+        .code(6)
+        .stderr(predicate::str::contains(
+            "/base_urls: must have at least 1 item(s)",
+        ));
+    cli()
+        .args(["diff", &fixture("config.json"), &fixture("config.ini")])
+        .assert()
+        .success()
+        .stdout("");
+}
@@ -0,0 +1,206 @@
+//! The library as another workspace crate would use it
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    path::PathBuf,
+    sync::Arc,
+    thread,
+};
+
+use config_reader::{
+    Config, ConfigDeserializer, ConfigSource, Error, FormatRegistry, JsonDeserializer,
+    LayeredLoader, LoadOptions, OwnedConfig, Visit, YmlDeserializer, load_config, load_sources,
+    source,
+};
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(name)
+}
+
+fn read(name: &str) -> String {
+    std::fs::read_to_string(fixture(name)).unwrap()
+}
+
+#[test]
+fn it_reads_every_fixture_the_same() {
+    let registry = FormatRegistry::default();
+    let configs: Vec<OwnedConfig> = ["config.json", "config.yml", "config.toml", "config.ini"]
+        .iter()
+        .map(|name| {
+            let extension = name.rsplit('.').next().unwrap();
+            let format = registry.by_extension(extension).unwrap();
+            let contents = read(name);
+            let config: Config = format.deserialize(&contents).unwrap();
+            config.into_owned()
+        })
+        .collect();
+
+    assert_eq!(configs[0].port, 1234);
+    assert_eq!(configs[0].base_url(), "https://config.teach-rs.tweede.golf");
+    assert_eq!(
+        configs[0].database.url.expose(),
+        "postgresql://user@database:5432/db"
+    );
+    assert!(configs.iter().all(|config| *config == configs[0]));
+}
+
+#[test]
+fn it_uses_the_formats_directly() {
+    let json = read("config.json");
+    let yaml = read("config.yml");
+    let from_json: Config = (&JsonDeserializer::new() as &dyn ConfigDeserializer)
+        .deserialize(&json)
+        .unwrap();
+    let from_yaml: Config = (&YmlDeserializer::new() as &dyn ConfigDeserializer)
+        .deserialize(&yaml)
+        .unwrap();
+    assert_eq!(from_json, from_yaml);
+    assert!(from_json.validate().is_ok());
+}
+
+/// A format defined outside of the crate, `key := value` lines
+struct Assignments;
+
+impl ConfigDeserializer for Assignments {
+    fn name(&self) -> &'static str {
+        "assignments"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["assign"]
+    }
+
+    fn mime_types(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    fn deserialize_erased<'de>(
+        &self,
+        contents: &'de str,
+        visit: Visit<'de, '_>,
+    ) -> Result<(), Error> {
+        let map: serde_json::Map<String, serde_json::Value> = contents
+            .lines()
+            .filter_map(|line| line.split_once(":="))
+            .map(|(k, v)| {
+                let v = v.trim();
+                let value = v.parse::<u64>().map_or_else(|_| v.into(), Into::into);
+                (k.trim().to_string(), value)
+            })
+            .collect();
+        let value = serde_json::Value::Object(map);
+        visit(&mut <dyn erased_serde::Deserializer>::erase(value))
+            .map_err(|e| Error::Invalid(e.to_string()))
+    }
+}
+
+#[test]
+fn it_takes_formats_from_other_crates() {
+    let mut registry = FormatRegistry::default();
+    registry.register(Arc::new(Assignments));
+
+    let contents = "port := 8080\nbase_url := https://example.com\n\
+                    s3_path := bucket\ndatabase_url := postgresql://db/app\n";
+    let format = registry.by_name("assign").unwrap();
+    let config: Config = format.deserialize(contents).unwrap();
+    assert_eq!(config.port, 8080);
+}
+
+#[test]
+fn it_layers_files_and_overrides() {
+    let base = read("config.yml");
+    let loader = LayeredLoader::new()
+        .add_contents("config.yml", &YmlDeserializer::new(), &base)
+        .unwrap()
+        .add_layer("override", serde_json::json!({"port": 9000}));
+    let config = loader.config().unwrap();
+    assert_eq!(config.port, 9000);
+    assert_eq!(loader.provenance()["port"], "override");
+    assert_eq!(loader.provenance()["base_urls"], "config.yml");
+}
+
+#[test]
+fn it_loads_like_the_binary_does() {
+    let registry = FormatRegistry::default();
+    let inputs = [fixture("config.toml").display().to_string()];
+    let options = LoadOptions {
+        with_provenance: true,
+        ..Default::default()
+    };
+    let loaded = load_config(&registry, &inputs, options).unwrap();
+    assert_eq!(loaded.config.port, 1234);
+    assert_eq!(loaded.provenance.unwrap()["port"], inputs[0]);
+}
+
+#[test]
+fn it_loads_from_http_and_memory() {
+    // A stand-in for a config service, serving the TOML fixture once
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/config", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        // Wait for the whole request, up to the blank line after the headers
+        let mut request = BufReader::new(&stream);
+        let mut line = String::new();
+        while request.read_line(&mut line).unwrap() > 2 {
+            line.clear();
+        }
+        let body = read("config.toml");
+        let response = format!("HTTP/1.0 200 OK\r\nContent-Type: application/toml\r\n\r\n{body}");
+        stream.write_all(response.as_bytes()).unwrap();
+    });
+
+    let registry = FormatRegistry::default();
+    let sources: Vec<Box<dyn ConfigSource>> = vec![
+        Box::new(source::Http::new(&url).unwrap()),
+        Box::new(source::Inline::new("overrides", "port: 9000").extension("yml")),
+    ];
+    let options = LoadOptions {
+        with_provenance: true,
+        ..Default::default()
+    };
+    let loaded = load_sources(&registry, &sources, options).unwrap();
+    server.join().unwrap();
+
+    assert_eq!(loaded.config.port, 9000);
+    let provenance = loaded.provenance.unwrap();
+    assert_eq!(provenance["port"], "overrides");
+    assert_eq!(provenance["s3.bucket"], url);
+}
+
+#[test]
+fn it_reports_where_errors_are() {
+    let registry = FormatRegistry::default();
+    let dir = std::env::temp_dir().join(format!("config_reader_library_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("broken.yml");
+    std::fs::write(&path, read("config.yml").replace("1234", "nope")).unwrap();
+
+    let inputs = [path.display().to_string()];
+    let err = load_config(&registry, &inputs, LoadOptions::default())
+        .err()
+        .unwrap();
+    assert!(matches!(err.error(), Error::Yaml(_)));
+    assert_eq!(err.key(), Some("port"));
+    assert_eq!(err.path(), Some(inputs[0].as_str()));
+    assert_eq!(err.exit_code(), 5);
+}
+
+#[test]
+fn it_unescapes_single_plain_files() {
+    // Nothing to merge or resolve but the escape, which still has to be undone
+    let contents = read("config.yml").replace(
+        "bucket.teach-rs.tweede.golf",
+        "bucket-$${literally}.teach-rs.tweede.golf",
+    );
+    let sources: Vec<Box<dyn ConfigSource>> = vec![Box::new(
+        source::Inline::new("config", contents).extension("yml"),
+    )];
+    let loaded =
+        load_sources(&FormatRegistry::default(), &sources, LoadOptions::default()).unwrap();
+    assert_eq!(
+        loaded.config.s3.bucket,
+        "bucket-${literally}.teach-rs.tweede.golf"
+    );
+}